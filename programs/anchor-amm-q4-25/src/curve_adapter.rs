@@ -0,0 +1,245 @@
+//! The only module that talks to `constant_product_curve`. Everything else
+//! goes through these functions and our own types, so a change in the
+//! library's API is contained here and a change in its behavior is caught by
+//! the tests below.
+//!
+//! The module is public, so programs depending on this crate with the
+//! `no-entrypoint` feature can price swaps locally, without a CPI or a
+//! simulated transaction, through `compute_swap_output`.
+
+use anchor_lang::prelude::*;
+use constant_product_curve::{ConstantProduct, LiquidityPair};
+
+use crate::{errors::AmmError, math::compute_fee};
+
+/// Decimal precision the curve computes with unless a pool's authority
+/// changes it with `set_precision`, see `Config::precision`.
+pub const DEFAULT_PRECISION: u8 = 6;
+
+/// Rejects with `InvalidPrecision` a `precision` the curve library cannot
+/// compute with. The library checks the precision when a curve is built, so
+/// one is built over fixed reserves and any error it returns is taken as its
+/// verdict on the precision; the answer never depends on a pool's state.
+pub fn check_precision(precision: u8) -> Result<()> {
+    ConstantProduct::init(1, 1, 1, 0, Some(precision)).map_err(|_| AmmError::InvalidPrecision)?;
+
+    Ok(())
+}
+
+/// Result of pricing a swap on the curve.
+#[derive(Debug, PartialEq)]
+pub struct SwapOutcome {
+    pub amount_out: u64, // Output the curve pays for the input
+    pub fee_paid: u64,   // Input kept as fee: by the curve itself in `swap`, so 0
+}
+
+/// Token amounts a deposit takes or a withdrawal pays out.
+#[derive(Debug, PartialEq)]
+pub struct LiquidityAmounts {
+    pub x: u64,
+    pub y: u64,
+}
+
+/// Prices selling `amount_in` of X (`is_x`) or Y into reserves
+/// `reserve_x`/`reserve_y`, computing at `precision` decimals. The curve is
+/// run without a fee and without a minimum output; both are applied by the
+/// caller.
+pub fn swap(
+    reserve_x: u64,
+    reserve_y: u64,
+    is_x: bool,
+    amount_in: u64,
+    precision: u8,
+) -> Result<SwapOutcome> {
+    let mut curve = ConstantProduct::init(
+        reserve_x,
+        reserve_y,
+        0, // LP supply not needed for swap
+        0,
+        Some(precision),
+    )
+    .map_err(AmmError::from)?;
+
+    let pair = match is_x {
+        true => LiquidityPair::X,
+        false => LiquidityPair::Y,
+    };
+
+    let result = curve.swap(pair, amount_in, 0).map_err(AmmError::from)?;
+
+    Ok(SwapOutcome {
+        amount_out: result.withdraw,
+        fee_paid: result.fee,
+    })
+}
+
+/// Output of selling `amount_in` into a pool holding `reserve_in` of the input
+/// token and `reserve_out` of the output token, at a fee of `fee_bps`. The
+/// fee is taken from the input, rounded up, and returned as `fee_paid`; the
+/// rest is priced on the curve at `DEFAULT_PRECISION`. This is the pool's base
+/// fee only: surcharges, fee changes, virtual reserves and a changed precision
+/// of a live pool are for the caller to apply, so the `quote` view remains the
+/// exact answer for a given pool.
+pub fn compute_swap_output(
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in: u64,
+    fee_bps: u16,
+) -> Result<SwapOutcome> {
+    require!(fee_bps <= 10_000, AmmError::InvalidFee);
+
+    let fee_paid = compute_fee(amount_in, fee_bps);
+    require!(amount_in > fee_paid, AmmError::AmountTooSmall);
+
+    let outcome = swap(
+        reserve_in,
+        reserve_out,
+        true,
+        amount_in - fee_paid,
+        DEFAULT_PRECISION,
+    )?;
+
+    Ok(SwapOutcome {
+        amount_out: outcome.amount_out,
+        fee_paid,
+    })
+}
+
+/// X and Y that minting `lp` LP tokens takes from a pool holding `vault_x`,
+/// `vault_y` and `supply` LP tokens.
+pub fn deposit_amounts(
+    vault_x: u64,
+    vault_y: u64,
+    supply: u64,
+    lp: u64,
+) -> Result<LiquidityAmounts> {
+    let amounts =
+        ConstantProduct::xy_deposit_amounts_from_l(vault_x, vault_y, supply, lp, DEFAULT_PRECISION)
+            .map_err(AmmError::from)?;
+
+    Ok(LiquidityAmounts {
+        x: amounts.x,
+        y: amounts.y,
+    })
+}
+
+/// X and Y that burning `lp` LP tokens pays out of a pool holding `vault_x`,
+/// `vault_y` and `supply` LP tokens.
+pub fn withdraw_amounts(
+    vault_x: u64,
+    vault_y: u64,
+    supply: u64,
+    lp: u64,
+) -> Result<LiquidityAmounts> {
+    let amounts = ConstantProduct::xy_withdraw_amounts_from_l(
+        vault_x,
+        vault_y,
+        supply,
+        lp,
+        DEFAULT_PRECISION,
+    )
+    .map_err(AmmError::from)?;
+
+    Ok(LiquidityAmounts {
+        x: amounts.x,
+        y: amounts.y,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{constant_product_out, STRICT_VERIFY_TOLERANCE};
+
+    #[test]
+    fn swap_pins_exact_outputs() {
+        // Inputs where `reserve_out * amount_in / (reserve_in + amount_in)` is
+        // a whole number, so any rounding choice gives the same answer
+        let outcome = swap(1_000, 1_000, true, 1_000, DEFAULT_PRECISION).unwrap();
+        assert_eq!(outcome.amount_out, 500);
+        assert_eq!(outcome.fee_paid, 0);
+
+        let outcome = swap(1_000_000, 4_000_000, true, 1_000_000, DEFAULT_PRECISION).unwrap();
+        assert_eq!(outcome.amount_out, 2_000_000);
+
+        let outcome = swap(
+            100_000_000,
+            50_000_000,
+            false,
+            50_000_000,
+            DEFAULT_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(outcome.amount_out, 50_000_000);
+    }
+
+    #[test]
+    fn check_precision_leaves_the_bounds_to_the_library() {
+        assert!(check_precision(DEFAULT_PRECISION).is_ok());
+        // 10^255 fits no integer the library could scale by
+        assert_eq!(
+            check_precision(u8::MAX).unwrap_err(),
+            AmmError::InvalidPrecision.into()
+        );
+    }
+
+    #[test]
+    fn swap_stays_within_tolerance_of_in_crate_math() {
+        for (reserve_x, reserve_y, amount_in) in [
+            (100_000_000, 100_000_000, 9_900_000),
+            (1_000, 1_000_000, 10),
+            (1_000_000_000_000, 3_000_000_000_000, 123_456_789),
+        ] {
+            let outcome = swap(reserve_x, reserve_y, true, amount_in, DEFAULT_PRECISION).unwrap();
+            let expected = constant_product_out(reserve_x, reserve_y, amount_in, 0).unwrap();
+
+            assert!(outcome.amount_out.abs_diff(expected) <= STRICT_VERIFY_TOLERANCE);
+            assert_eq!(outcome.fee_paid, 0);
+        }
+    }
+
+    #[test]
+    fn compute_swap_output_takes_the_fee_from_the_input() {
+        // 1% of 1_010_102 is 10_101.02, rounded up, leaving exactly 1_000_000
+        let outcome = compute_swap_output(1_000_000, 1_000_000, 1_010_102, 100).unwrap();
+        assert_eq!(
+            outcome,
+            SwapOutcome {
+                amount_out: 500_000,
+                fee_paid: 10_102,
+            }
+        );
+
+        let outcome = compute_swap_output(1_000, 1_000, 1_000, 0).unwrap();
+        assert_eq!(outcome.amount_out, 500);
+        assert_eq!(outcome.fee_paid, 0);
+
+        for (reserve_in, reserve_out, amount_in, fee_bps) in [
+            (100_000_000, 100_000_000, 10_000_000, 100),
+            (1_000, 1_000_000, 10, 30),
+            (100_000_000, 50_000_000, 1_000_000, 0),
+        ] {
+            let outcome = compute_swap_output(reserve_in, reserve_out, amount_in, fee_bps).unwrap();
+            let expected = constant_product_out(reserve_in, reserve_out, amount_in, fee_bps);
+
+            assert!(outcome.amount_out.abs_diff(expected.unwrap()) <= STRICT_VERIFY_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn compute_swap_output_rejects_fees_that_leave_nothing() {
+        assert!(compute_swap_output(1_000, 1_000, 1_000, 10_001).is_err());
+        assert!(compute_swap_output(1_000, 1_000, 1_000, 10_000).is_err());
+        assert!(compute_swap_output(1_000, 1_000, 0, 30).is_err());
+    }
+
+    #[test]
+    fn liquidity_amounts_are_pro_rata() {
+        let half = LiquidityAmounts { x: 500, y: 1_000 };
+        assert_eq!(deposit_amounts(1_000, 2_000, 1_000, 500).unwrap(), half);
+        assert_eq!(withdraw_amounts(1_000, 2_000, 1_000, 500).unwrap(), half);
+
+        let all = LiquidityAmounts { x: 1_000, y: 2_000 };
+        assert_eq!(withdraw_amounts(1_000, 2_000, 1_000, 1_000).unwrap(), all);
+    }
+}
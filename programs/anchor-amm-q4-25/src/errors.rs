@@ -1,44 +1,99 @@
 use anchor_lang::error_code;
 use constant_product_curve::CurveError;
 
+// Clients match on the numeric codes (6000 + discriminant), so every variant
+// carries an explicit discriminant. New variants are appended with the next
+// number; existing variants are never reordered, renumbered or removed.
 #[error_code]
 pub enum AmmError {
     #[msg("DefaultError")]
-    DefaultError,
+    DefaultError = 0,
     #[msg("Offer expired.")]
-    OfferExpired,
+    OfferExpired = 1,
     #[msg("This pool is locked.")]
-    PoolLocked,
+    PoolLocked = 2,
     #[msg("Slippage exceeded.")]
-    SlippageExceeded,
+    SlippageExceeded = 3,
     #[msg("Overflow detected.")]
-    Overflow,
+    Overflow = 4,
     #[msg("Underflow detected.")]
-    Underflow,
+    Underflow = 5,
     #[msg("Invalid token.")]
-    InvalidToken,
+    InvalidToken = 6,
     #[msg("Actual liquidity is less than minimum.")]
-    LiquidityLessThanMinimum,
+    LiquidityLessThanMinimum = 7,
     #[msg("No liquidity in pool.")]
-    NoLiquidityInPool,
+    NoLiquidityInPool = 8,
     #[msg("Bump error.")]
-    BumpError,
+    BumpError = 9,
     #[msg("Curve error.")]
-    CurveError,
+    CurveError = 10,
     #[msg("Fee is greater than 100%. This is not a very good deal.")]
-    InvalidFee,
+    InvalidFee = 11,
     #[msg("Invalid update authority.")]
-    InvalidAuthority,
+    InvalidAuthority = 12,
     #[msg("No update authority set.")]
-    NoAuthoritySet,
+    NoAuthoritySet = 13,
     #[msg("Invalid amount.")]
-    InvalidAmount,
+    InvalidAmount = 14,
     #[msg("Invalid precision.")]
-    InvalidPrecision,
+    InvalidPrecision = 15,
     #[msg("Insufficient balance.")]
-    InsufficientBalance,
+    InsufficientBalance = 16,
     #[msg("Zero balance.")]
-    ZeroBalance,
+    ZeroBalance = 17,
+    #[msg("Curve output does not match the independent recomputation.")]
+    CurveMismatch = 18,
+    #[msg("Pool reserves are too large to price safely.")]
+    ReservesTooLarge = 19,
+    #[msg("Swap amount does not cover the minimum fee.")]
+    AmountTooSmall = 20,
+    #[msg("This swap direction is locked.")]
+    DirectionLocked = 21,
+    #[msg("Deposit would exceed the pool's TVL cap.")]
+    TvlCapExceeded = 22,
+    #[msg("No quote mint set.")]
+    NoQuoteMint = 23,
+    #[msg("Pool already has liquidity.")]
+    PoolHasLiquidity = 24,
+    #[msg("Swap would move the reserve ratio outside the configured bounds.")]
+    RatioOutOfBounds = 25,
+    #[msg("Nonce already used or out of order.")]
+    NonceReused = 26,
+    #[msg("Missing or invalid ed25519 signature.")]
+    InvalidSignature = 27,
+    #[msg("Source and target pool are the same.")]
+    SamePool = 28,
+    #[msg("Seed does not match the canonical seed for this mint pair and fee.")]
+    SeedMismatch = 29,
+    #[msg("Deposit amounts are too far off the pool's ratio.")]
+    UnbalancedDeposit = 30,
+    #[msg("Calling program is not allowed to swap against this pool.")]
+    CallerNotAllowed = 31,
+    #[msg("Position still holds LP tokens.")]
+    PositionNotEmpty = 32,
+    #[msg("Fee was changed too recently.")]
+    FeeUpdateTooFrequent = 33,
+    #[msg("Initializer has reached its pool limit.")]
+    PoolLimitReached = 34,
+    #[msg("Minting these LP tokens would overflow the LP supply.")]
+    LpSupplyOverflow = 35,
+    #[msg("No fee mint and pricing pool set.")]
+    NoFeeMint = 36,
+    #[msg("Token account is not owned by the token program.")]
+    InvalidAccountOwner = 37,
+    #[msg("NFT is not a verified member of the discount collection.")]
+    InvalidNft = 38,
+    #[msg("Pools must be passed as config, vault X, vault Y and LP mint groups.")]
+    InvalidPoolAccounts = 39,
+    #[msg("Token account is frozen.")]
+    AccountFrozen = 40,
+    #[msg("Pool must be locked for this change.")]
+    PoolNotLocked = 41,
+    #[msg("Swap would exceed the pool's volume cap for the current window.")]
+    VolumeCapExceeded = 42,
+    #[msg("Too many allowed callers.")]
+    TooManyCallers = 43,
 }
 
 impl From<CurveError> for AmmError {
@@ -54,3 +109,56 @@ impl From<CurveError> for AmmError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes_are_stable() {
+        assert_eq!(u32::from(AmmError::DefaultError), 6000);
+        assert_eq!(u32::from(AmmError::OfferExpired), 6001);
+        assert_eq!(u32::from(AmmError::PoolLocked), 6002);
+        assert_eq!(u32::from(AmmError::SlippageExceeded), 6003);
+        assert_eq!(u32::from(AmmError::Overflow), 6004);
+        assert_eq!(u32::from(AmmError::Underflow), 6005);
+        assert_eq!(u32::from(AmmError::InvalidToken), 6006);
+        assert_eq!(u32::from(AmmError::LiquidityLessThanMinimum), 6007);
+        assert_eq!(u32::from(AmmError::NoLiquidityInPool), 6008);
+        assert_eq!(u32::from(AmmError::BumpError), 6009);
+        assert_eq!(u32::from(AmmError::CurveError), 6010);
+        assert_eq!(u32::from(AmmError::InvalidFee), 6011);
+        assert_eq!(u32::from(AmmError::InvalidAuthority), 6012);
+        assert_eq!(u32::from(AmmError::NoAuthoritySet), 6013);
+        assert_eq!(u32::from(AmmError::InvalidAmount), 6014);
+        assert_eq!(u32::from(AmmError::InvalidPrecision), 6015);
+        assert_eq!(u32::from(AmmError::InsufficientBalance), 6016);
+        assert_eq!(u32::from(AmmError::ZeroBalance), 6017);
+        assert_eq!(u32::from(AmmError::CurveMismatch), 6018);
+        assert_eq!(u32::from(AmmError::ReservesTooLarge), 6019);
+        assert_eq!(u32::from(AmmError::AmountTooSmall), 6020);
+        assert_eq!(u32::from(AmmError::DirectionLocked), 6021);
+        assert_eq!(u32::from(AmmError::TvlCapExceeded), 6022);
+        assert_eq!(u32::from(AmmError::NoQuoteMint), 6023);
+        assert_eq!(u32::from(AmmError::PoolHasLiquidity), 6024);
+        assert_eq!(u32::from(AmmError::RatioOutOfBounds), 6025);
+        assert_eq!(u32::from(AmmError::NonceReused), 6026);
+        assert_eq!(u32::from(AmmError::InvalidSignature), 6027);
+        assert_eq!(u32::from(AmmError::SamePool), 6028);
+        assert_eq!(u32::from(AmmError::SeedMismatch), 6029);
+        assert_eq!(u32::from(AmmError::UnbalancedDeposit), 6030);
+        assert_eq!(u32::from(AmmError::CallerNotAllowed), 6031);
+        assert_eq!(u32::from(AmmError::PositionNotEmpty), 6032);
+        assert_eq!(u32::from(AmmError::FeeUpdateTooFrequent), 6033);
+        assert_eq!(u32::from(AmmError::PoolLimitReached), 6034);
+        assert_eq!(u32::from(AmmError::LpSupplyOverflow), 6035);
+        assert_eq!(u32::from(AmmError::NoFeeMint), 6036);
+        assert_eq!(u32::from(AmmError::InvalidAccountOwner), 6037);
+        assert_eq!(u32::from(AmmError::InvalidNft), 6038);
+        assert_eq!(u32::from(AmmError::InvalidPoolAccounts), 6039);
+        assert_eq!(u32::from(AmmError::AccountFrozen), 6040);
+        assert_eq!(u32::from(AmmError::PoolNotLocked), 6041);
+        assert_eq!(u32::from(AmmError::VolumeCapExceeded), 6042);
+        assert_eq!(u32::from(AmmError::TooManyCallers), 6043);
+    }
+}
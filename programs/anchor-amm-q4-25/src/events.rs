@@ -0,0 +1,128 @@
+use anchor_lang::{prelude::*, solana_program::log::sol_log_data};
+
+use crate::instructions::ConfigUpdate;
+
+#[event]
+pub struct ConfigUpdated {
+    pub config: Pubkey,       // Config that was updated
+    pub update: ConfigUpdate, // Fields that were set; `None` fields were left unchanged
+}
+
+#[event]
+pub struct PrecisionChanged {
+    pub config: Pubkey,    // Pool whose precision was changed
+    pub old_precision: u8, // Precision swaps were priced with until now
+    pub new_precision: u8, // Precision swaps are priced with from now on
+}
+
+#[event]
+pub struct SwapEvent {
+    pub config: Pubkey,  // Pool that was swapped against
+    pub user: Pubkey,    // Account that sold and received the tokens
+    pub is_x: bool,      // If X was sold for Y, same meaning as in `swap`
+    pub amount_in: u64,  // Input transferred into the vault, fee included
+    pub amount_out: u64, // Output transferred to the user
+    pub fee: u64,        // Fee kept in the vault, in the output token with `fee_on_output`
+    pub sequence: u64,   // Position of the swap among all swaps on `config`, from 1, gapless
+}
+
+#[event]
+pub struct FeeTokenPaid {
+    pub config: Pubkey,   // Pool that was swapped against
+    pub user: Pubkey,     // Account that paid the fee
+    pub fee_mint: Pubkey, // Token the fee was paid in
+    pub amount: u64,      // Fee transferred to the fee recipient
+}
+
+#[event]
+pub struct DustSwept {
+    pub config: Pubkey, // Pool the dust was swept from
+    pub amount_x: u64,  // Token X moved to the treasury
+    pub amount_y: u64,  // Token Y moved to the treasury
+}
+
+/// First byte of a compact swap log. Together with the fixed length it tells
+/// these records apart from Anchor events on the same `Program data:` lines.
+/// Records tagged `0x01` are the earlier 26-byte layout, the same fields
+/// without `sequence`.
+pub const COMPACT_SWAP_TAG: u8 = 0x02;
+
+/// Length in bytes of a compact swap log.
+pub const COMPACT_SWAP_LEN: usize = 34;
+
+/// Packs a swap into the fixed layout logged through `sol_log_data` when
+/// `config.compact_logs` is set, instead of emitting a `SwapEvent`. It appears
+/// as a single base64 `Program data:` line. All integers are little-endian:
+///
+/// | offset | size | field                               |
+/// |--------|------|-------------------------------------|
+/// | 0      | 1    | `COMPACT_SWAP_TAG`                  |
+/// | 1      | 1    | direction, 1 if X was sold          |
+/// | 2      | 8    | `amount_in` (u64), fee included     |
+/// | 10     | 8    | `amount_out` (u64)                  |
+/// | 18     | 8    | `fee` (u64), as in `SwapEvent`      |
+/// | 26     | 8    | `sequence` (u64), as in `SwapEvent` |
+///
+/// The pool and user are not logged; indexers take them from the
+/// instruction's `config` and `user` accounts.
+pub fn compact_swap_log(
+    is_x: bool,
+    amount_in: u64,
+    amount_out: u64,
+    fee: u64,
+    sequence: u64,
+) -> [u8; COMPACT_SWAP_LEN] {
+    let mut data = [0u8; COMPACT_SWAP_LEN];
+    data[0] = COMPACT_SWAP_TAG;
+    data[1] = is_x as u8;
+    data[2..10].copy_from_slice(&amount_in.to_le_bytes());
+    data[10..18].copy_from_slice(&amount_out.to_le_bytes());
+    data[18..26].copy_from_slice(&fee.to_le_bytes());
+    data[26..34].copy_from_slice(&sequence.to_le_bytes());
+    data
+}
+
+/// Emits `event`, or logs it in the compact layout when `compact` is set.
+pub fn log_swap(event: SwapEvent, compact: bool) {
+    match compact {
+        true => sol_log_data(&[&compact_swap_log(
+            event.is_x,
+            event.amount_in,
+            event.amount_out,
+            event.fee,
+            event.sequence,
+        )]),
+        false => emit!(event),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_swap_log_uses_fixed_offsets() {
+        let data = compact_swap_log(true, 1_000_000, 990_000, u64::MAX, 7);
+
+        assert_eq!(data[0], COMPACT_SWAP_TAG);
+        assert_eq!(data[1], 1);
+        assert_eq!(data[2..10], 1_000_000u64.to_le_bytes());
+        assert_eq!(data[10..18], 990_000u64.to_le_bytes());
+        assert_eq!(data[18..26], [0xff; 8]);
+        assert_eq!(data[26..34], 7u64.to_le_bytes());
+        assert_eq!(compact_swap_log(false, 0, 0, 0, 0)[1], 0);
+    }
+
+    #[test]
+    fn compact_swap_log_carries_the_sequence() {
+        // A new tag, so indexers can tell it from the 26-byte layout without a sequence
+        assert_ne!(COMPACT_SWAP_TAG, 0x01);
+        assert_eq!(COMPACT_SWAP_LEN, 34);
+
+        let data = compact_swap_log(false, 1, 1, 0, u64::MAX - 1);
+        assert_eq!(
+            u64::from_le_bytes(data[26..34].try_into().unwrap()),
+            u64::MAX - 1
+        );
+    }
+}
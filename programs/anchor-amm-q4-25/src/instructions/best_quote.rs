@@ -0,0 +1,101 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::get_associated_token_address,
+    token::{Mint, TokenAccount},
+};
+
+use crate::{errors::AmmError, state::Config};
+
+/// Result of `best_quote`, returned through `set_return_data`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BestQuote {
+    pub config: Pubkey,  // Pool that pays the most for the input
+    pub amount_out: u64, // Output `swap` on that pool would pay right now
+}
+
+#[derive(Accounts)]
+pub struct CompareTiers<'info> {
+    pub mint_x: Account<'info, Mint>,
+    pub mint_y: Account<'info, Mint>,
+}
+
+impl<'info> CompareTiers<'info> {
+    /// Quotes `amount_in` on each of `pools` and returns the pool that pays
+    /// the most, so a router can pick between a pair's fee tiers in one call.
+    ///
+    /// Pools come as remaining accounts in `[config, vault_x, vault_y, mint_lp]`
+    /// groups, and every one must be over `mint_x` and `mint_y` in that
+    /// order. Each is checked and priced through `Config::check_swap`, as a
+    /// `swap` with no minimum output would be right now, so pools that swap
+    /// would be rejected on are skipped. Of two equal quotes the pool passed
+    /// first wins.
+    pub fn best_quote(
+        &self,
+        pools: &'info [AccountInfo<'info>],
+        is_x: bool,
+        amount_in: u64,
+    ) -> Result<BestQuote> {
+        require!(
+            !pools.is_empty() && pools.len() % 4 == 0,
+            AmmError::InvalidPoolAccounts
+        );
+
+        let clock = Clock::get()?;
+        let mut best: Option<BestQuote> = None;
+        for pool in pools.chunks(4) {
+            let config = Account::<Config>::try_from(&pool[0])?;
+            require!(
+                config.mint_x == self.mint_x.key() && config.mint_y == self.mint_y.key(),
+                AmmError::InvalidToken
+            );
+            require_keys_eq!(
+                pool[1].key(),
+                get_associated_token_address(&config.key(), &config.mint_x),
+                AmmError::InvalidPoolAccounts
+            );
+            require_keys_eq!(
+                pool[2].key(),
+                get_associated_token_address(&config.key(), &config.mint_y),
+                AmmError::InvalidPoolAccounts
+            );
+            let mint_lp = Pubkey::create_program_address(
+                &[b"lp", config.key().as_ref(), &[config.lp_bump]],
+                &crate::ID,
+            )
+            .map_err(|_| AmmError::InvalidPoolAccounts)?;
+            require_keys_eq!(pool[3].key(), mint_lp, AmmError::InvalidPoolAccounts);
+            let vault_x = Account::<TokenAccount>::try_from(&pool[1])?;
+            let vault_y = Account::<TokenAccount>::try_from(&pool[2])?;
+            let mint_lp = Account::<Mint>::try_from(&pool[3])?;
+
+            let Ok(checked) = config.check_swap(
+                vault_x.amount,
+                vault_y.amount,
+                mint_lp.supply,
+                is_x,
+                amount_in,
+                0,
+                0,
+                &clock,
+            ) else {
+                continue;
+            };
+            let amount_out = checked.amounts.amount_out;
+
+            let better = match &best {
+                Some(best) => amount_out > best.amount_out,
+                None => true,
+            };
+            if better {
+                best = Some(BestQuote {
+                    config: config.key(),
+                    amount_out,
+                });
+            }
+        }
+
+        let best = best.ok_or(AmmError::NoLiquidityInPool)?;
+
+        Ok(best)
+    }
+}
@@ -0,0 +1,69 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::get_associated_token_address,
+    token::{Mint, TokenAccount},
+};
+
+use crate::{
+    errors::AmmError,
+    state::{Config, Position},
+};
+
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Account<'info, Mint>,
+    /// CHECK: the owner's LP associated token account, pinned by address; it
+    /// may have been closed, so it is read in `close_position`
+    #[account(
+        address = get_associated_token_address(&owner.key(), &mint_lp.key())
+            @ AmmError::PositionNotEmpty,
+    )]
+    pub owner_lp: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+        has_one = config,
+        seeds = [b"position", config.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+    )]
+    pub position: Account<'info, Position>,
+}
+
+impl<'info> ClosePosition<'info> {
+    /// Closes the owner's position PDA and returns its rent once they hold no
+    /// LP tokens of the pool. Swap fees accrue to the reserves and are paid out
+    /// through the LP tokens, so a position never has fees of its own left to
+    /// collect; an empty LP balance is all there is to check. A later deposit
+    /// opens a fresh position.
+    ///
+    /// Only the owner's associated LP token account is checked. It must be
+    /// passed even once closed, and counts as empty only if it holds no
+    /// lamports and no data or is a token account with no LP tokens. Any
+    /// other account in its place, such as the program ID a client sends for
+    /// an account it leaves out, cannot show the balance and is refused with
+    /// `PositionNotEmpty`. LP tokens held in any other account are not looked
+    /// at, so this guards against closing a live position by mistake, not
+    /// against an owner set on closing it.
+    pub fn close_position(&mut self) -> Result<()> {
+        let owner_lp = self.owner_lp.to_account_info();
+        let balance = match owner_lp.lamports() == 0 && owner_lp.data_is_empty() {
+            true => 0,
+            false => Account::<TokenAccount>::try_from(&owner_lp)?.amount,
+        };
+        require!(balance == 0, AmmError::PositionNotEmpty);
+
+        Ok(())
+    }
+}
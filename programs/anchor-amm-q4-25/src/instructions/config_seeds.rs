@@ -0,0 +1,19 @@
+use anchor_lang::prelude::*;
+
+use crate::state::Config;
+
+#[derive(Accounts)]
+pub struct ReadConfig<'info> {
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+impl<'info> ReadConfig<'info> {
+    /// Seeds that sign as `config`, see `Config::signer_seeds`.
+    pub fn config_seeds(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self.config.signer_seeds())
+    }
+}
@@ -3,9 +3,13 @@ use anchor_spl::{
     associated_token::AssociatedToken,
     token::{mint_to, transfer, Mint, MintTo, Token, TokenAccount, Transfer},
 };
-use constant_product_curve::ConstantProduct;
 
-use crate::{errors::AmmError, state::Config};
+use crate::{
+    curve_adapter::deposit_amounts,
+    errors::AmmError,
+    math::{integer_sqrt, spot_price, DEPOSIT_RATIO_TOLERANCE_BPS},
+    state::{Config, Position},
+};
 
 #[derive(Accounts)]
 pub struct Deposit<'info> {
@@ -14,6 +18,7 @@ pub struct Deposit<'info> {
     pub mint_x: Account<'info, Mint>,
     pub mint_y: Account<'info, Mint>,
     #[account(
+        mut,
         has_one = mint_x,
         has_one = mint_y,
         seeds = [b"config", config.seed.to_le_bytes().as_ref()],
@@ -57,6 +62,14 @@ pub struct Deposit<'info> {
         associated_token::authority = user,
     )]
     pub user_lp: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = Position::DISCRIMINATOR.len() + Position::INIT_SPACE,
+        seeds = [b"position", config.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub position: Account<'info, Position>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub associated_token_program: Program<'info, AssociatedToken>,
@@ -68,30 +81,72 @@ impl<'info> Deposit<'info> {
         amount: u64, // Amount of LP tokens that the user wants to "claim"
         max_x: u64,  // Maximum amount of token X that the user is willing to deposit
         max_y: u64,  // Maximum amount of token Y that the user is willing to deposit
+        bumps: DepositBumps,
     ) -> Result<()> {
         require!(self.config.locked == false, AmmError::PoolLocked);
         require!(amount != 0, AmmError::InvalidAmount);
+        // Fail here rather than deep inside the mint CPI
+        self.mint_lp
+            .supply
+            .checked_add(amount)
+            .ok_or(AmmError::LpSupplyOverflow)?;
 
-        let (x, y) = match self.mint_lp.supply == 0
-            && self.vault_x.amount == 0
-            && self.vault_y.amount == 0
-        {
+        // Close the oracle interval before the reserves change
+        let (reserve_x, reserve_y) = self
+            .config
+            .effective_reserves(self.vault_x.amount, self.vault_y.amount)?;
+        self.config
+            .update_oracle(reserve_x, reserve_y, Clock::get()?.unix_timestamp);
+
+        // Priced against what the LPs are owed, rounding dust left out
+        let (lp_x, lp_y) = self
+            .config
+            .lp_reserves(self.vault_x.amount, self.vault_y.amount);
+        let first_deposit = self.mint_lp.supply == 0 && lp_x == 0 && lp_y == 0;
+        let (x, y) = match first_deposit {
             true => (max_x, max_y),
             false => {
-                let amounts = ConstantProduct::xy_deposit_amounts_from_l(
-                    self.vault_x.amount,
-                    self.vault_y.amount,
-                    self.mint_lp.supply,
-                    amount,
-                    6,
-                )
-                .unwrap();
+                let amounts = deposit_amounts(lp_x, lp_y, self.mint_lp.supply, amount)?;
                 (amounts.x, amounts.y)
             }
         };
 
         require!(x <= max_x && y <= max_y, AmmError::SlippageExceeded);
 
+        // Enforce the phased-rollout TVL caps
+        let vault_x_after = self
+            .vault_x
+            .amount
+            .checked_add(x)
+            .ok_or(AmmError::Overflow)?;
+        let vault_y_after = self
+            .vault_y
+            .amount
+            .checked_add(y)
+            .ok_or(AmmError::Overflow)?;
+        require!(
+            vault_x_after <= self.config.max_tvl_x && vault_y_after <= self.config.max_tvl_y,
+            AmmError::TvlCapExceeded
+        );
+
+        // Record the entry price impermanent loss is measured against
+        let (reserve_x_after, reserve_y_after) = self
+            .config
+            .effective_reserves(vault_x_after, vault_y_after)?;
+        let entry_price =
+            spot_price(reserve_x_after, reserve_y_after).ok_or(AmmError::NoLiquidityInPool)?;
+        if first_deposit {
+            self.config
+                .check_opening_price(reserve_x, reserve_y, entry_price)?;
+        }
+        self.position.owner = self.user.key();
+        self.position.config = self.config.key();
+        self.position.bump = bumps.position;
+        self.position
+            .record_deposit(entry_price, self.user_lp.amount, amount)?;
+
+        self.config.track_reserves(x, y, 0, 0);
+
         // deposit token x
         self.deposit_tokens(true, x)?;
         // deposit token y
@@ -100,6 +155,41 @@ impl<'info> Deposit<'info> {
         self.mint_lp_tokens(amount)
     }
 
+    /// Deposits as much of `max_x` and `max_y` as fits the pool's current
+    /// ratio and mints the LP tokens that is worth, see `balanced_deposit`.
+    ///
+    /// By default the excess on the side that does not run out is simply not
+    /// taken, so it stays with the user instead of being donated to the pool.
+    /// With `strict_ratio` set, the deposit is rejected with `UnbalancedDeposit`
+    /// instead if more than `DEPOSIT_RATIO_TOLERANCE_BPS` of either amount would
+    /// be left over.
+    pub fn deposit_balanced(
+        &mut self,
+        max_x: u64,
+        max_y: u64,
+        min_lp: u64, // Minimum amount of LP tokens to receive
+        strict_ratio: bool,
+        bumps: DepositBumps,
+    ) -> Result<()> {
+        let (lp_x, lp_y) = self
+            .config
+            .lp_reserves(self.vault_x.amount, self.vault_y.amount);
+        let (lp, x, y) = balanced_deposit(lp_x, lp_y, self.mint_lp.supply, max_x, max_y)?;
+        require!(lp != 0 && lp >= min_lp, AmmError::SlippageExceeded);
+
+        if strict_ratio {
+            let within_tolerance = |used: u64, max: u64| {
+                (max - used) as u128 * 10_000 <= max as u128 * DEPOSIT_RATIO_TOLERANCE_BPS as u128
+            };
+            require!(
+                within_tolerance(x, max_x) && within_tolerance(y, max_y),
+                AmmError::UnbalancedDeposit
+            );
+        }
+
+        self.deposit(lp, x, y, bumps)
+    }
+
     pub fn deposit_tokens(&self, is_x: bool, amount: u64) -> Result<()> {
         let (from, to) = match is_x {
             true => (
@@ -145,3 +235,40 @@ impl<'info> Deposit<'info> {
         mint_to(ctx, amount)
     }
 }
+
+/// LP tokens that at most `max_x` and `max_y` can mint against a pool holding
+/// `vault_x` and `vault_y` with `supply` LP tokens, and the X and Y that deposit
+/// takes. The side that runs out first bounds the LP tokens, rounded down. An
+/// empty pool takes both amounts as is, opening at their ratio, and mints
+/// `sqrt(x * y)` LP tokens.
+pub fn balanced_deposit(
+    vault_x: u64,
+    vault_y: u64,
+    supply: u64,
+    max_x: u64,
+    max_y: u64,
+) -> Result<(u64, u64, u64)> {
+    if supply == 0 && vault_x == 0 && vault_y == 0 {
+        let lp = integer_sqrt(max_x as u128 * max_y as u128) as u64;
+        return Ok((lp, max_x, max_y));
+    }
+    require!(
+        supply != 0 && vault_x != 0 && vault_y != 0,
+        AmmError::NoLiquidityInPool
+    );
+
+    let lp_for_x = max_x as u128 * supply as u128 / vault_x as u128;
+    let lp_for_y = max_y as u128 * supply as u128 / vault_y as u128;
+    let lp = u64::try_from(lp_for_x.min(lp_for_y)).map_err(|_| AmmError::Overflow)?;
+    if lp == 0 {
+        return Ok((0, 0, 0));
+    }
+
+    let amounts = deposit_amounts(vault_x, vault_y, supply, lp)?;
+    require!(
+        amounts.x <= max_x && amounts.y <= max_y,
+        AmmError::SlippageExceeded
+    );
+
+    Ok((lp, amounts.x, amounts.y))
+}
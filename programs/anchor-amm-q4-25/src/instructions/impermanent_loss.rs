@@ -0,0 +1,59 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+use crate::{
+    errors::AmmError,
+    math::{impermanent_loss, spot_price},
+    state::{Config, Position},
+};
+
+#[derive(Accounts)]
+pub struct ImpermanentLoss<'info> {
+    pub mint_x: Account<'info, Mint>,
+    pub mint_y: Account<'info, Mint>,
+    #[account(
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+    )]
+    pub vault_x: Account<'info, TokenAccount>,
+    #[account(
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+    )]
+    pub vault_y: Account<'info, TokenAccount>,
+    #[account(
+        has_one = config,
+        seeds = [b"position", config.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump,
+    )]
+    pub position: Account<'info, Position>,
+}
+
+impl<'info> ImpermanentLoss<'info> {
+    /// Impermanent loss of `position` at the current spot price, as a fraction
+    /// of the value of just holding the deposited tokens, scaled by
+    /// `PRICE_SCALE` (so `PRICE_SCALE` is a 100% loss).
+    ///
+    /// Only the price move since the entry price is counted: swap fees earned
+    /// by the position are ignored, and several deposits at different prices
+    /// are treated as one deposit at their LP-weighted average price.
+    pub fn impermanent_loss(&self) -> Result<u64> {
+        let (x, y) = self
+            .config
+            .effective_reserves(self.vault_x.amount, self.vault_y.amount)?;
+        let current_price = spot_price(x, y).ok_or(AmmError::NoLiquidityInPool)?;
+
+        let loss =
+            impermanent_loss(self.position.entry_price, current_price).ok_or(AmmError::Overflow)?;
+        let loss = u64::try_from(loss).map_err(|_| AmmError::Overflow)?;
+
+        Ok(loss)
+    }
+}
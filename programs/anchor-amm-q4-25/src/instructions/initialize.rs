@@ -4,7 +4,11 @@ use anchor_spl::{
     token::{Mint, Token, TokenAccount},
 };
 
-use crate::state::Config;
+use crate::{
+    curve_adapter::DEFAULT_PRECISION,
+    errors::AmmError,
+    state::{Config, PoolCounter},
+};
 
 #[derive(Accounts)]
 #[instruction(seed: u64)]
@@ -43,7 +47,15 @@ pub struct Initialize<'info> {
         bump,
         space = Config::DISCRIMINATOR.len() + Config::INIT_SPACE,
     )]
-    pub config: Account<'info, Config>,
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        init_if_needed,
+        payer = initializer,
+        space = PoolCounter::DISCRIMINATOR.len() + PoolCounter::INIT_SPACE,
+        seeds = [b"pool_counter", initializer.key().as_ref()],
+        bump,
+    )]
+    pub pool_counter: Box<Account<'info, PoolCounter>>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
@@ -57,13 +69,71 @@ impl<'info> Initialize<'info> {
         authority: Option<Pubkey>,
         bumps: InitializeBumps,
     ) -> Result<()> {
+        // The seed is not a free choice: deriving it from the pair and fee tier
+        // keeps two pools from ever contending for the same config PDA
+        require!(
+            seed == Config::canonical_seed(&self.mint_x.key(), &self.mint_y.key(), fee),
+            AmmError::SeedMismatch
+        );
+
+        self.pool_counter
+            .open(self.initializer.key(), bumps.pool_counter);
+        self.pool_counter.record_pool()?;
+
         self.config.set_inner(Config {
             seed,
             authority,
             mint_x: self.mint_x.key(),
             mint_y: self.mint_y.key(),
+            quote_mint: None,
             fee,
+            same_slot_surcharge_bps: 0,
+            impact_multiplier_bps: 0,
+            max_fee_bps: 10_000,
+            fee_on_output: false,
+            min_fee_absolute: 0,
+            max_tvl_x: u64::MAX,
+            max_tvl_y: u64::MAX,
+            daily_volume_cap: u64::MAX,
+            min_ratio: 0,
+            max_ratio: u64::MAX,
+            target_ratio: 0,
+            rebalance_strength: 0,
+            virtual_reserve_x: 0,
+            virtual_reserve_y: 0,
+            tracked_reserve_x: 0,
+            tracked_reserve_y: 0,
+            accrue_fees: false,
+            fees_accrued_x: 0,
+            fees_accrued_y: 0,
             locked: false,
+            lock_x_to_y: false,
+            lock_y_to_x: false,
+            price_cumulative_x: 0,
+            price_cumulative_y: 0,
+            last_update_ts: Clock::get()?.unix_timestamp,
+            last_price_x: 0,
+            volatility_bps: 0,
+            last_swap_slot: 0,
+            sequence: 0,
+            volume_window_start: 0,
+            window_volume: 0,
+            last_fee_update_ts: 0,
+            dust_x: 0,
+            dust_y: 0,
+            compact_logs: false,
+            strict_verify: false,
+            precision: DEFAULT_PRECISION,
+            allowed_callers: Vec::new(),
+            fee_exempt_caller: None,
+            fee_exempt_until: 0,
+            fee_mint: None,
+            fee_pricing_pool: None,
+            fee_price_cumulative: 0,
+            fee_price_ts: 0,
+            fee_price: 0,
+            discount_collection: None,
+            nft_discount_bps: 0,
             config_bump: bumps.config,
             lp_bump: bumps.mint_lp,
         });
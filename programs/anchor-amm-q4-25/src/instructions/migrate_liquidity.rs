@@ -0,0 +1,295 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::{burn, mint_to, transfer, Burn, Mint, MintTo, Token, TokenAccount, Transfer},
+};
+
+use crate::{
+    curve_adapter::withdraw_amounts,
+    errors::AmmError,
+    instructions::balanced_deposit,
+    math::spot_price,
+    state::{Config, Position},
+};
+
+#[derive(Accounts)]
+pub struct MigrateLiquidity<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: Box<Account<'info, Mint>>,
+    pub mint_y: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"config", source_config.seed.to_le_bytes().as_ref()],
+        bump = source_config.config_bump,
+    )]
+    pub source_config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"lp", source_config.key().as_ref()],
+        bump = source_config.lp_bump,
+    )]
+    pub source_mint_lp: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = source_config,
+    )]
+    pub source_vault_x: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = source_config,
+    )]
+    pub source_vault_y: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = source_mint_lp,
+        associated_token::authority = user,
+    )]
+    pub user_source_lp: Box<Account<'info, TokenAccount>>,
+    // has_one on both configs is what makes the two pools share their mints
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"config", target_config.seed.to_le_bytes().as_ref()],
+        bump = target_config.config_bump,
+    )]
+    pub target_config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"lp", target_config.key().as_ref()],
+        bump = target_config.lp_bump,
+    )]
+    pub target_mint_lp: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = target_config,
+    )]
+    pub target_vault_x: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = target_config,
+    )]
+    pub target_vault_y: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = target_mint_lp,
+        associated_token::authority = user,
+    )]
+    pub user_target_lp: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = Position::DISCRIMINATOR.len() + Position::INIT_SPACE,
+        seeds = [b"position", target_config.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub target_position: Box<Account<'info, Position>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+    )]
+    pub user_x: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+    )]
+    pub user_y: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MigrateLiquidity<'info> {
+    /// Burns `amount` source LP tokens and deposits the underlying straight
+    /// into the target pool, vault to vault, in one instruction. As many
+    /// target LP tokens are minted as the withdrawn X and Y cover at the
+    /// target's ratio, and what does not fit that ratio is returned to the
+    /// user. An empty target pool opens at the source's ratio and mints
+    /// `sqrt(x * y)` LP tokens.
+    pub fn migrate_liquidity(
+        &mut self,
+        amount: u64,        // Amount of source LP tokens to burn
+        min_target_lp: u64, // Minimum amount of target LP tokens to receive
+        bumps: MigrateLiquidityBumps,
+    ) -> Result<()> {
+        require_keys_neq!(
+            self.source_config.key(),
+            self.target_config.key(),
+            AmmError::SamePool
+        );
+        // Leaving a locked pool is allowed, like `withdraw`; entering one is not
+        require!(self.target_config.locked == false, AmmError::PoolLocked);
+        require!(amount != 0, AmmError::InvalidAmount);
+        require!(self.source_mint_lp.supply != 0, AmmError::NoLiquidityInPool);
+
+        // Close both oracle intervals before the reserves change
+        let now = Clock::get()?.unix_timestamp;
+        let (reserve_x, reserve_y) = self
+            .source_config
+            .effective_reserves(self.source_vault_x.amount, self.source_vault_y.amount)?;
+        self.source_config.update_oracle(reserve_x, reserve_y, now);
+        let (reserve_x, reserve_y) = self
+            .target_config
+            .effective_reserves(self.target_vault_x.amount, self.target_vault_y.amount)?;
+        self.target_config.update_oracle(reserve_x, reserve_y, now);
+
+        // Everything the source LP tokens are worth, both sides priced against
+        // what the LPs are owed, rounding dust left out
+        let (source_x, source_y) = self
+            .source_config
+            .lp_reserves(self.source_vault_x.amount, self.source_vault_y.amount);
+        let withdrawn = withdraw_amounts(source_x, source_y, self.source_mint_lp.supply, amount)?;
+
+        let (target_x, target_y) = self
+            .target_config
+            .lp_reserves(self.target_vault_x.amount, self.target_vault_y.amount);
+        let (target_lp, x, y) = balanced_deposit(
+            target_x,
+            target_y,
+            self.target_mint_lp.supply,
+            withdrawn.x,
+            withdrawn.y,
+        )?;
+        require!(
+            target_lp != 0 && target_lp >= min_target_lp,
+            AmmError::SlippageExceeded
+        );
+        self.target_mint_lp
+            .supply
+            .checked_add(target_lp)
+            .ok_or(AmmError::LpSupplyOverflow)?;
+
+        // Enforce the target's phased-rollout TVL caps
+        let vault_x_after = self
+            .target_vault_x
+            .amount
+            .checked_add(x)
+            .ok_or(AmmError::Overflow)?;
+        let vault_y_after = self
+            .target_vault_y
+            .amount
+            .checked_add(y)
+            .ok_or(AmmError::Overflow)?;
+        require!(
+            vault_x_after <= self.target_config.max_tvl_x
+                && vault_y_after <= self.target_config.max_tvl_y,
+            AmmError::TvlCapExceeded
+        );
+
+        // Record the entry price impermanent loss is measured against
+        let (reserve_x_after, reserve_y_after) = self
+            .target_config
+            .effective_reserves(vault_x_after, vault_y_after)?;
+        let entry_price =
+            spot_price(reserve_x_after, reserve_y_after).ok_or(AmmError::NoLiquidityInPool)?;
+        if self.target_mint_lp.supply == 0 {
+            self.target_config
+                .check_opening_price(reserve_x, reserve_y, entry_price)?;
+        }
+        self.target_position.owner = self.user.key();
+        self.target_position.config = self.target_config.key();
+        self.target_position.bump = bumps.target_position;
+        self.target_position
+            .record_deposit(entry_price, self.user_target_lp.amount, target_lp)?;
+
+        self.source_config.release_fees(
+            amount,
+            self.source_mint_lp.supply,
+            withdrawn.x,
+            withdrawn.y,
+        )?;
+        self.source_config
+            .track_reserves(0, 0, withdrawn.x, withdrawn.y);
+        self.target_config.track_reserves(x, y, 0, 0);
+
+        self.burn_source_lp(amount)?;
+
+        // Move the deposit vault to vault and return the remainder
+        self.transfer_from_source(true, &self.target_vault_x, x)?;
+        self.transfer_from_source(false, &self.target_vault_y, y)?;
+        self.transfer_from_source(true, &self.user_x, withdrawn.x - x)?;
+        self.transfer_from_source(false, &self.user_y, withdrawn.y - y)?;
+
+        self.mint_target_lp(target_lp)
+    }
+
+    pub fn burn_source_lp(&self, amount: u64) -> Result<()> {
+        let cpi_program = self.token_program.to_account_info();
+
+        let cpi_accounts = Burn {
+            mint: self.source_mint_lp.to_account_info(),
+            from: self.user_source_lp.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+
+        let ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+        burn(ctx, amount)
+    }
+
+    pub fn transfer_from_source(
+        &self,
+        is_x: bool,
+        to: &Account<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let from = match is_x {
+            true => self.source_vault_x.to_account_info(),
+            false => self.source_vault_y.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+
+        let cpi_accounts = Transfer {
+            from,
+            to: to.to_account_info(),
+            authority: self.source_config.to_account_info(),
+        };
+
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"config",
+            &self.source_config.seed.to_le_bytes(),
+            &[self.source_config.config_bump],
+        ]];
+
+        let ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+
+        transfer(ctx, amount)
+    }
+
+    pub fn mint_target_lp(&self, amount: u64) -> Result<()> {
+        let cpi_program = self.token_program.to_account_info();
+
+        let cpi_accounts = MintTo {
+            mint: self.target_mint_lp.to_account_info(),
+            to: self.user_target_lp.to_account_info(),
+            authority: self.target_config.to_account_info(),
+        };
+
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"config",
+            &self.target_config.seed.to_le_bytes(),
+            &[self.target_config.config_bump],
+        ]];
+
+        let ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+
+        mint_to(ctx, amount)
+    }
+}
@@ -1,9 +1,41 @@
+pub mod best_quote;
+pub mod close_position;
+pub mod config_seeds;
 pub mod deposit;
+pub mod impermanent_loss;
 pub mod initialize;
+pub mod migrate_liquidity;
+pub mod poke;
+pub mod pool_health;
+pub mod pool_limit;
+pub mod quote;
+pub mod staged_swap;
 pub mod swap;
+pub mod swap_with_fee_token;
+pub mod swap_with_nft_discount;
+pub mod swap_with_signature;
+pub mod sweep_dust;
+pub mod update;
+pub mod virtual_reserves;
 pub mod withdraw;
 
+pub use best_quote::*;
+pub use close_position::*;
+pub use config_seeds::*;
 pub use deposit::*;
+pub use impermanent_loss::*;
 pub use initialize::*;
+pub use migrate_liquidity::*;
+pub use poke::*;
+pub use pool_health::*;
+pub use pool_limit::*;
+pub use quote::*;
+pub use staged_swap::*;
 pub use swap::*;
+pub use swap_with_fee_token::*;
+pub use swap_with_nft_discount::*;
+pub use swap_with_signature::*;
+pub use sweep_dust::*;
+pub use update::*;
+pub use virtual_reserves::*;
 pub use withdraw::*;
@@ -0,0 +1,42 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+use crate::state::Config;
+
+#[derive(Accounts)]
+pub struct Poke<'info> {
+    pub mint_x: Account<'info, Mint>,
+    pub mint_y: Account<'info, Mint>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+    )]
+    pub vault_x: Account<'info, TokenAccount>,
+    #[account(
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+    )]
+    pub vault_y: Account<'info, TokenAccount>,
+}
+
+impl<'info> Poke<'info> {
+    /// Lets keepers advance the price accumulators of a quiet pool without
+    /// trading, so TWAP consumers see fresh data between swaps.
+    pub fn poke(&mut self) -> Result<()> {
+        let (reserve_x, reserve_y) = self
+            .config
+            .effective_reserves(self.vault_x.amount, self.vault_y.amount)?;
+        self.config
+            .update_oracle(reserve_x, reserve_y, Clock::get()?.unix_timestamp);
+
+        Ok(())
+    }
+}
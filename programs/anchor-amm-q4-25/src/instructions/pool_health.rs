@@ -0,0 +1,73 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+use crate::{errors::AmmError, math::invariant, state::Config};
+
+/// Layout version of `PoolHealth`. Bumped whenever a field is added, removed
+/// or reordered, so monitors can tell which layout they are decoding.
+pub const POOL_HEALTH_VERSION: u8 = 1;
+
+/// Pool state for monitoring, returned by `pool_health` through
+/// `set_return_data`. Borsh encoded in field order, `version` first.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PoolHealth {
+    pub version: u8,       // `POOL_HEALTH_VERSION`
+    pub locked: bool,      // If the pool is locked
+    pub reserve_x: u64,    // Vault X balance, virtual reserves not included
+    pub reserve_y: u64,    // Vault Y balance, virtual reserves not included
+    pub lp_supply: u64,    // LP tokens in circulation
+    pub fee: u16,          // Swap fee in basis points
+    pub invariant_k: u128, // `reserve_x * reserve_y`
+    pub balanced: bool,    // If both vault balances match the tracked reserves
+}
+
+#[derive(Accounts)]
+pub struct CheckHealth<'info> {
+    pub mint_x: Account<'info, Mint>,
+    pub mint_y: Account<'info, Mint>,
+    #[account(
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Account<'info, Mint>,
+    #[account(
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+    )]
+    pub vault_x: Account<'info, TokenAccount>,
+    #[account(
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+    )]
+    pub vault_y: Account<'info, TokenAccount>,
+}
+
+impl<'info> CheckHealth<'info> {
+    /// Snapshot of the pool for keepers and dashboards. `balanced` goes false
+    /// once the vaults drift from what the pool's own instructions account
+    /// for, e.g. after a direct transfer into a vault.
+    pub fn pool_health(&self) -> Result<PoolHealth> {
+        let (reserve_x, reserve_y) = (self.vault_x.amount, self.vault_y.amount);
+        let invariant_k =
+            invariant(reserve_x as u128, reserve_y as u128).ok_or(AmmError::Overflow)?;
+
+        Ok(PoolHealth {
+            version: POOL_HEALTH_VERSION,
+            locked: self.config.locked,
+            reserve_x,
+            reserve_y,
+            lp_supply: self.mint_lp.supply,
+            fee: self.config.fee,
+            invariant_k,
+            balanced: reserve_x == self.config.tracked_reserve_x
+                && reserve_y == self.config.tracked_reserve_y,
+        })
+    }
+}
@@ -0,0 +1,49 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::AmmError, program::AnchorAmmQ425, state::PoolCounter};
+
+#[derive(Accounts)]
+#[instruction(initializer: Pubkey)]
+pub struct SetPoolLimit<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key()),
+    )]
+    pub program: Program<'info, AnchorAmmQ425>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ AmmError::InvalidAuthority,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = PoolCounter::DISCRIMINATOR.len() + PoolCounter::INIT_SPACE,
+        seeds = [b"pool_counter", initializer.as_ref()],
+        bump,
+    )]
+    pub pool_counter: Account<'info, PoolCounter>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SetPoolLimit<'info> {
+    /// Caps how many pools `initializer` may create, for curated deployments
+    /// where a leaked or rogue key should not be able to flood pool discovery
+    /// with spam pools and the rent-paid accounts indexers have to crawl.
+    /// Only the program's upgrade authority, acting as the program admin, can
+    /// set it. The cap is per signing key, so it bounds a known initializer
+    /// rather than stopping anyone from creating pools from fresh keys.
+    /// Lowering it below the pools already created only blocks new ones.
+    pub fn set_pool_limit(
+        &mut self,
+        initializer: Pubkey,
+        max_pools: u32,
+        bumps: SetPoolLimitBumps,
+    ) -> Result<()> {
+        self.pool_counter.open(initializer, bumps.pool_counter);
+        self.pool_counter.max_pools = max_pools;
+
+        Ok(())
+    }
+}
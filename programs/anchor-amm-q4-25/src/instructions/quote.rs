@@ -0,0 +1,184 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+use crate::{
+    errors::AmmError,
+    math::{apply_buffer, mul_div, price_impact_bps, spot_price},
+    state::{Config, PriceTarget},
+};
+
+/// Part of the slippage buffer every `SwapQuote::suggested_min_out` keeps,
+/// however small and calm the trade.
+pub const SLIPPAGE_BUFFER_BASE_BPS: u64 = 10;
+
+/// Cap on the slippage buffer, so a volatile pool never suggests accepting
+/// less than 90% of the quote.
+pub const SLIPPAGE_BUFFER_MAX_BPS: u64 = 1_000;
+
+/// Result of `quote`, returned through `set_return_data`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SwapQuote {
+    pub amount_out: u64,        // Output `swap` would pay right now, fee included
+    pub suggested_min_out: u64, // Default `min_amount_out`, `amount_out` less a buffer
+}
+
+#[derive(Accounts)]
+pub struct Quote<'info> {
+    pub mint_x: Account<'info, Mint>,
+    pub mint_y: Account<'info, Mint>,
+    #[account(
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+    )]
+    pub vault_x: Account<'info, TokenAccount>,
+    #[account(
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+    )]
+    pub vault_y: Account<'info, TokenAccount>,
+}
+
+impl<'info> Quote<'info> {
+    /// Input needed to push the spot price of X (in Y, scaled by `PRICE_SCALE`)
+    /// to `target_price`, see `Config::price_target`.
+    pub fn amount_to_reach_price(&self, target_price: u64) -> Result<PriceTarget> {
+        self.config
+            .price_target(self.vault_x.amount, self.vault_y.amount, target_price)
+    }
+
+    /// Output `swap` would pay for `amount_in` right now, fee included. Priced
+    /// exactly as the swap executes it, so passing `amount_out` as
+    /// `min_amount_out` accepts the current price and nothing worse.
+    ///
+    /// `suggested_min_out` is a default for clients that do not pick their
+    /// own slippage: `amount_out * (10_000 - buffer_bps) / 10_000`, rounded
+    /// down, with
+    ///
+    /// `buffer_bps = min(BASE + impact_bps / 2 + volatility_bps, MAX)`
+    ///
+    /// where `BASE` and `MAX` are `SLIPPAGE_BUFFER_BASE_BPS` and
+    /// `SLIPPAGE_BUFFER_MAX_BPS`, `impact_bps` is the trade's own price impact
+    /// (larger trades are worth more to front-run) and `volatility_bps` is the
+    /// pool's average price move between oracle intervals, which tracks how
+    /// far the price tends to move before a transaction lands.
+    pub fn quote(&self, is_x: bool, amount_in: u64) -> Result<SwapQuote> {
+        let (x, y) = self
+            .config
+            .effective_reserves(self.vault_x.amount, self.vault_y.amount)?;
+        require!(x != 0 && y != 0, AmmError::NoLiquidityInPool);
+
+        let reserve_in = match is_x {
+            true => x,
+            false => y,
+        };
+        let fee_bps = self
+            .config
+            .fee_bps(Clock::get()?.slot, x, y, is_x, amount_in);
+        let amounts = self
+            .config
+            .price_swap(x, y, is_x, amount_in, Some(fee_bps))?;
+
+        let buffer_bps = (SLIPPAGE_BUFFER_BASE_BPS + price_impact_bps(reserve_in, amount_in) / 2)
+            .saturating_add(self.config.volatility_bps)
+            .min(SLIPPAGE_BUFFER_MAX_BPS);
+
+        Ok(SwapQuote {
+            amount_out: amounts.amount_out,
+            suggested_min_out: apply_buffer(amounts.amount_out, buffer_bps),
+        })
+    }
+
+    /// Spot price of X in Y, scaled by `PRICE_SCALE` like `amount_to_reach_price`'s
+    /// target, that the pool would be left at after swapping `amount_in`. The
+    /// whole input, fee included, lands in the vault, so the post-swap reserves
+    /// are the input reserve plus `amount_in` and the output reserve minus the
+    /// output, less the fee with `accrue_fees`, see `Config::reserve_deltas`.
+    /// Nothing is executed.
+    pub fn price_after_swap(&self, is_x: bool, amount_in: u64) -> Result<u64> {
+        let (x, y) = self
+            .config
+            .effective_reserves(self.vault_x.amount, self.vault_y.amount)?;
+        require!(x != 0 && y != 0, AmmError::NoLiquidityInPool);
+
+        let fee_bps = self
+            .config
+            .fee_bps(Clock::get()?.slot, x, y, is_x, amount_in);
+        let amounts = self
+            .config
+            .price_swap(x, y, is_x, amount_in, Some(fee_bps))?;
+
+        let (added, removed) = self.config.reserve_deltas(amount_in, &amounts);
+        let (x_after, y_after) = match is_x {
+            true => (x.checked_add(added), y.checked_sub(removed)),
+            false => (x.checked_sub(removed), y.checked_add(added)),
+        };
+        let x_after = x_after.ok_or(AmmError::Overflow)?;
+        let y_after = y_after.ok_or(AmmError::Overflow)?;
+
+        let price = spot_price(x_after, y_after).ok_or(AmmError::NoLiquidityInPool)?;
+        let price = u64::try_from(price).map_err(|_| AmmError::Overflow)?;
+
+        Ok(price)
+    }
+
+    /// Output of swapping `amount_in`, valued in `config.quote_mint`.
+    ///
+    /// When the output token is the quote mint this is the output itself.
+    /// Otherwise the output is converted at the pool's own post-swap spot
+    /// price, `amount_out * reserve_quote / reserve_out`, rounded down. Only
+    /// this pool's price is used; chaining through other pools is left to clients.
+    pub fn quote_normalized(&self, is_x: bool, amount_in: u64) -> Result<u64> {
+        let quote_mint = self.config.quote_mint.ok_or(AmmError::NoQuoteMint)?;
+
+        let (x, y) = self
+            .config
+            .effective_reserves(self.vault_x.amount, self.vault_y.amount)?;
+        let fee_bps = self
+            .config
+            .fee_bps(Clock::get()?.slot, x, y, is_x, amount_in);
+        let amounts = self
+            .config
+            .price_swap(x, y, is_x, amount_in, Some(fee_bps))?;
+
+        let (added, removed) = self.config.reserve_deltas(amount_in, &amounts);
+        let (mint_out, reserve_in_after, reserve_out_after) = match is_x {
+            true => (
+                self.mint_y.key(),
+                x.checked_add(added),
+                y.checked_sub(removed),
+            ),
+            false => (
+                self.mint_x.key(),
+                y.checked_add(added),
+                x.checked_sub(removed),
+            ),
+        };
+
+        if mint_out == quote_mint {
+            return Ok(amounts.amount_out);
+        }
+
+        // The output is the non-quote token, so price it in the input (quote) token
+        let reserve_in_after = reserve_in_after.ok_or(AmmError::Overflow)?;
+        let reserve_out_after = reserve_out_after.ok_or(AmmError::InsufficientBalance)?;
+        require!(reserve_out_after != 0, AmmError::NoLiquidityInPool);
+
+        let value = mul_div(
+            amounts.amount_out as u128,
+            reserve_in_after as u128,
+            reserve_out_after as u128,
+        )
+        .ok_or(AmmError::Overflow)?;
+
+        let value = u64::try_from(value).map_err(|_| AmmError::Overflow)?;
+
+        Ok(value)
+    }
+}
@@ -0,0 +1,359 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::{get_associated_token_address, AssociatedToken},
+    token::{close_account, transfer, CloseAccount, Mint, Token, TokenAccount, Transfer},
+};
+
+use crate::{
+    errors::AmmError,
+    events::{log_swap, SwapEvent},
+    instructions::{check_token_owners, check_transfers},
+    state::{Config, StagedSwap},
+};
+
+#[derive(Accounts)]
+#[instruction(is_x: bool)]
+pub struct StageSwap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        constraint = mint_in.key() == config.mint_in(is_x) @ AmmError::InvalidToken,
+    )]
+    pub mint_in: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = StagedSwap::DISCRIMINATOR.len() + StagedSwap::INIT_SPACE,
+        seeds = [
+            b"staged_swap",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            StagedSwap::direction_seed(is_x).as_ref(),
+        ],
+        bump,
+    )]
+    pub staged_swap: Account<'info, StagedSwap>,
+    #[account(
+        mut,
+        associated_token::mint = mint_in,
+        associated_token::authority = user,
+    )]
+    pub user_in: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_in,
+        associated_token::authority = staged_swap,
+    )]
+    pub escrow: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> StageSwap<'info> {
+    /// Commits `amount` more input to the caller's staged swap against this
+    /// pool, moving it into the staged swap's escrow. Nothing is priced yet;
+    /// the whole accumulated input is swapped at once by `finalize_staged_swap`
+    /// against the reserves at that moment, or refunded by `cancel_staged_swap`.
+    pub fn stage_swap(&mut self, is_x: bool, amount: u64, bumps: StageSwapBumps) -> Result<()> {
+        require!(amount != 0, AmmError::InvalidAmount);
+
+        self.staged_swap.owner = self.user.key();
+        self.staged_swap.config = self.config.key();
+        self.staged_swap.is_x = is_x;
+        self.staged_swap.bump = bumps.staged_swap;
+        self.staged_swap.amount_in = self
+            .staged_swap
+            .amount_in
+            .checked_add(amount)
+            .ok_or(AmmError::Overflow)?;
+
+        let cpi_accounts = Transfer {
+            from: self.user_in.to_account_info(),
+            to: self.escrow.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+        let ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+
+        transfer(ctx, amount)
+    }
+}
+
+#[derive(Accounts)]
+pub struct FinalizeStagedSwap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub mint_x: Account<'info, Mint>,
+    pub mint_y: Account<'info, Mint>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+    )]
+    pub vault_x: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+    )]
+    pub vault_y: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+    )]
+    pub user_x: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+    )]
+    pub user_y: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        close = user,
+        has_one = config,
+        constraint = staged_swap.owner == user.key() @ AmmError::InvalidAuthority,
+        seeds = [
+            b"staged_swap",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            StagedSwap::direction_seed(staged_swap.is_x).as_ref(),
+        ],
+        bump = staged_swap.bump,
+    )]
+    pub staged_swap: Account<'info, StagedSwap>,
+    #[account(
+        mut,
+        address = get_associated_token_address(
+            &staged_swap.key(),
+            &config.mint_in(staged_swap.is_x),
+        ),
+    )]
+    pub escrow: Account<'info, TokenAccount>,
+    /// CHECK: the instructions sysvar, pinned by address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> FinalizeStagedSwap<'info> {
+    /// Swaps everything staged as one trade, priced now against the current
+    /// reserves and checked exactly like `swap`, and closes the staged swap
+    /// and its escrow. The reserves may have moved since the input was
+    /// staged, so `min_amount_out` applies to the whole fill. Anything else
+    /// in the escrow, such as tokens sent to it directly, is refunded to the
+    /// user before it is closed.
+    pub fn finalize_staged_swap(&mut self, min_amount_out: u64) -> Result<()> {
+        self.config.check_caller(&self.instructions)?;
+        check_token_owners(
+            &self.token_program.key(),
+            &[
+                self.vault_x.to_account_info(),
+                self.vault_y.to_account_info(),
+                self.user_x.to_account_info(),
+                self.user_y.to_account_info(),
+                self.escrow.to_account_info(),
+            ],
+        )?;
+
+        let is_x = self.staged_swap.is_x;
+        let amount_in = self.staged_swap.amount_in;
+
+        let amounts = self.config.execute_swap(
+            self.vault_x.amount,
+            self.vault_y.amount,
+            self.mint_lp.supply,
+            is_x,
+            amount_in,
+            min_amount_out,
+            0,
+        )?;
+
+        let (vault_in, vault_out, user_in, user_out) = match is_x {
+            true => (&self.vault_x, &self.vault_y, &self.user_x, &self.user_y),
+            false => (&self.vault_y, &self.vault_x, &self.user_y, &self.user_x),
+        };
+        check_transfers(
+            &self.escrow,
+            vault_in,
+            vault_out,
+            user_out,
+            amount_in,
+            amounts.amount_out,
+        )?;
+        let refund = self.escrow.amount - amount_in;
+        if refund != 0 {
+            require!(!user_in.is_frozen(), AmmError::AccountFrozen);
+        }
+
+        let config_key = self.config.key();
+        let user_key = self.user.key();
+        let direction = StagedSwap::direction_seed(is_x);
+        let staged_seeds: &[&[&[u8]]] = &[&[
+            b"staged_swap",
+            config_key.as_ref(),
+            user_key.as_ref(),
+            direction.as_ref(),
+            &[self.staged_swap.bump],
+        ]];
+        let config_seeds: &[&[&[u8]]] = &[&[
+            b"config",
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ]];
+        let token_program = self.token_program.to_account_info();
+
+        // Escrowed input into the pool, then the output to the user
+        let cpi_accounts = Transfer {
+            from: self.escrow.to_account_info(),
+            to: vault_in.to_account_info(),
+            authority: self.staged_swap.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, staged_seeds);
+        transfer(ctx, amount_in)?;
+
+        let cpi_accounts = Transfer {
+            from: vault_out.to_account_info(),
+            to: user_out.to_account_info(),
+            authority: self.config.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, config_seeds);
+        transfer(ctx, amounts.amount_out)?;
+
+        // The escrow has to be empty to close
+        if refund != 0 {
+            let cpi_accounts = Transfer {
+                from: self.escrow.to_account_info(),
+                to: user_in.to_account_info(),
+                authority: self.staged_swap.to_account_info(),
+            };
+            let ctx =
+                CpiContext::new_with_signer(token_program.clone(), cpi_accounts, staged_seeds);
+            transfer(ctx, refund)?;
+        }
+
+        let cpi_accounts = CloseAccount {
+            account: self.escrow.to_account_info(),
+            destination: self.user.to_account_info(),
+            authority: self.staged_swap.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(token_program, cpi_accounts, staged_seeds);
+        close_account(ctx)?;
+
+        log_swap(
+            SwapEvent {
+                config: config_key,
+                user: user_key,
+                is_x,
+                amount_in,
+                amount_out: amounts.amount_out,
+                fee: amounts.fee,
+                sequence: self.config.sequence,
+            },
+            self.config.compact_logs,
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CancelStagedSwap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        close = user,
+        has_one = config,
+        constraint = staged_swap.owner == user.key() @ AmmError::InvalidAuthority,
+        seeds = [
+            b"staged_swap",
+            config.key().as_ref(),
+            user.key().as_ref(),
+            StagedSwap::direction_seed(staged_swap.is_x).as_ref(),
+        ],
+        bump = staged_swap.bump,
+    )]
+    pub staged_swap: Account<'info, StagedSwap>,
+    #[account(
+        mut,
+        address = get_associated_token_address(
+            &staged_swap.key(),
+            &config.mint_in(staged_swap.is_x),
+        ),
+    )]
+    pub escrow: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = get_associated_token_address(
+            &user.key(),
+            &config.mint_in(staged_swap.is_x),
+        ),
+    )]
+    pub user_in: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CancelStagedSwap<'info> {
+    /// Refunds everything in the escrow to the user and closes the staged
+    /// swap and its escrow, returning their rent.
+    pub fn cancel_staged_swap(&mut self) -> Result<()> {
+        let config_key = self.config.key();
+        let user_key = self.user.key();
+        let direction = StagedSwap::direction_seed(self.staged_swap.is_x);
+        let staged_seeds: &[&[&[u8]]] = &[&[
+            b"staged_swap",
+            config_key.as_ref(),
+            user_key.as_ref(),
+            direction.as_ref(),
+            &[self.staged_swap.bump],
+        ]];
+        let token_program = self.token_program.to_account_info();
+
+        let cpi_accounts = Transfer {
+            from: self.escrow.to_account_info(),
+            to: self.user_in.to_account_info(),
+            authority: self.staged_swap.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, staged_seeds);
+        transfer(ctx, self.escrow.amount)?;
+
+        let cpi_accounts = CloseAccount {
+            account: self.escrow.to_account_info(),
+            destination: self.user.to_account_info(),
+            authority: self.staged_swap.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(token_program, cpi_accounts, staged_seeds);
+
+        close_account(ctx)
+    }
+}
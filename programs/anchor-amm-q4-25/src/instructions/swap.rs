@@ -3,9 +3,20 @@ use anchor_spl::{
     associated_token::AssociatedToken,
     token::{transfer, Mint, Token, TokenAccount, Transfer},
 };
-use constant_product_curve::{ConstantProduct, LiquidityPair};
 
-use crate::{errors::AmmError, state::Config};
+use crate::{
+    errors::AmmError,
+    events::{log_swap, SwapEvent},
+    state::Config,
+};
+
+/// How much of a `swap_with_stop` input was swapped, returned through
+/// `set_return_data`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SwapFill {
+    pub filled: u64,   // Input swapped, fee included
+    pub refunded: u64, // Input left with the user because the stop price was reached
+}
 
 #[derive(Accounts)]
 pub struct Swap<'info> {
@@ -14,12 +25,18 @@ pub struct Swap<'info> {
     pub mint_x: Account<'info, Mint>,
     pub mint_y: Account<'info, Mint>,
     #[account(
+        mut,
         has_one = mint_x,
         has_one = mint_y,
         seeds = [b"config", config.seed.to_le_bytes().as_ref()],
         bump = config.config_bump,
     )]
     pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Account<'info, Mint>,
     #[account(
         mut,
         associated_token::mint = mint_x,
@@ -46,6 +63,9 @@ pub struct Swap<'info> {
         associated_token::authority = user,
     )]
     pub user_y: Account<'info, TokenAccount>,
+    /// CHECK: the instructions sysvar, pinned by address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
@@ -53,39 +73,136 @@ pub struct Swap<'info> {
 
 impl<'info> Swap<'info> {
     pub fn swap(&mut self, is_x: bool, amount_in: u64, min_amount_out: u64) -> Result<()> {
-        require!(self.config.locked == false, AmmError::PoolLocked);
-        require!(amount_in != 0, AmmError::InvalidAmount);
+        self.config.check_caller(&self.instructions)?;
+        let fee_discount_bps = self.exemption_discount()?;
+
+        self.settle_swap(is_x, amount_in, min_amount_out, fee_discount_bps)
+    }
+
+    /// Fee discount from `Config::fee_exempt`: the whole fee for the exempt
+    /// caller inside its window, nothing otherwise.
+    pub fn exemption_discount(&self) -> Result<u16> {
+        let fee_exempt = self
+            .config
+            .fee_exempt(&self.instructions, Clock::get()?.unix_timestamp)?;
+
+        Ok(match fee_exempt {
+            true => 10_000,
+            false => 0,
+        })
+    }
 
-        // Create a ConstantProduct curve instance
-        let mut curve = ConstantProduct::init(
+    /// Prices the swap through `Config::execute_swap`, moves both sides and
+    /// logs it. Callers run the caller checks first.
+    ///
+    /// The input is deposited before the output is withdrawn, in two CPIs.
+    /// Should the second fail, the whole transaction reverts and the deposit
+    /// with it, so a swap never half-happens. Everything that would make
+    /// either transfer fail is still checked up front, see `check_transfers`,
+    /// so such a swap fails with a pool error before any funds move rather
+    /// than with a token program error halfway through.
+    pub fn settle_swap(
+        &mut self,
+        is_x: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        fee_discount_bps: u16,
+    ) -> Result<()> {
+        self.check_token_owners()?;
+
+        let amounts = self.config.execute_swap(
             self.vault_x.amount,
             self.vault_y.amount,
-            0, // LP supply not needed for swap
-            self.config.fee,
-            Some(6),
-        )
-        .map_err(|_| AmmError::CurveError)?;
+            self.mint_lp.supply,
+            is_x,
+            amount_in,
+            min_amount_out,
+            fee_discount_bps,
+        )?;
+        self.check_transfers(is_x, amount_in, amounts.amount_out)?;
 
-        // Determine which pair we're swapping
-        let pair = if is_x {
-            LiquidityPair::X
-        } else {
-            LiquidityPair::Y
+        // Deposit tokens from user to vault, fee included
+        self.deposit_tokens(is_x, amount_in)?;
+
+        // Withdraw tokens from vault to user
+        self.withdraw_tokens(is_x, amounts.amount_out)?;
+
+        log_swap(
+            SwapEvent {
+                config: self.config.key(),
+                user: self.user.key(),
+                is_x,
+                amount_in,
+                amount_out: amounts.amount_out,
+                fee: amounts.fee,
+                sequence: self.config.sequence,
+            },
+            self.config.compact_logs,
+        );
+
+        Ok(())
+    }
+
+    /// Swaps up to `amount_in`, but only as much as moves the spot price of X
+    /// (in Y, scaled by `PRICE_SCALE`) to `stop_price`, up to rounding. Selling
+    /// X only fills while the price is above the stop and selling Y while it is
+    /// below, so a pool already at or past the stop fills nothing. The unfilled
+    /// input is never taken from the user, which refunds it; `min_amount_out`
+    /// applies to the filled part.
+    pub fn swap_with_stop(
+        &mut self,
+        is_x: bool,
+        amount_in: u64,
+        stop_price: u64,
+        min_amount_out: u64,
+    ) -> Result<SwapFill> {
+        require!(amount_in != 0, AmmError::InvalidAmount);
+
+        let target =
+            self.config
+                .price_target(self.vault_x.amount, self.vault_y.amount, stop_price)?;
+        let filled = match target.is_x == is_x {
+            true => amount_in.min(target.amount_in),
+            false => 0,
         };
 
-        // Perform the swap calculation
-        let result = curve
-            .swap(pair, amount_in, min_amount_out)
-            .map_err(AmmError::from)?;
+        if filled == 0 {
+            require!(min_amount_out == 0, AmmError::SlippageExceeded);
+        } else {
+            self.swap(is_x, filled, min_amount_out)?;
+        }
 
-        require!(result.deposit != 0, AmmError::InvalidAmount);
-        require!(result.withdraw != 0, AmmError::InvalidAmount);
+        Ok(SwapFill {
+            filled,
+            refunded: amount_in - filled,
+        })
+    }
 
-        // Deposit tokens from user to vault
-        self.deposit_tokens(is_x, amount_in)?;
+    /// Rejects any of the four token accounts the swap moves funds through
+    /// unless the token program owns it, see `check_token_owners`.
+    pub fn check_token_owners(&self) -> Result<()> {
+        check_token_owners(
+            &self.token_program.key(),
+            &[
+                self.vault_x.to_account_info(),
+                self.vault_y.to_account_info(),
+                self.user_x.to_account_info(),
+                self.user_y.to_account_info(),
+            ],
+        )
+    }
 
-        // Withdraw tokens from vault to user
-        self.withdraw_tokens(is_x, result.withdraw)
+    /// Rejects a swap either of whose transfers the token program would
+    /// refuse, see `check_transfers`.
+    pub fn check_transfers(&self, is_x: bool, amount_in: u64, amount_out: u64) -> Result<()> {
+        let (user_in, vault_in, vault_out, user_out) = match is_x {
+            true => (&self.user_x, &self.vault_x, &self.vault_y, &self.user_y),
+            false => (&self.user_y, &self.vault_y, &self.vault_x, &self.user_x),
+        };
+
+        check_transfers(
+            user_in, vault_in, vault_out, user_out, amount_in, amount_out,
+        )
     }
 
     pub fn deposit_tokens(&self, is_x: bool, amount: u64) -> Result<()> {
@@ -145,3 +262,40 @@ impl<'info> Swap<'info> {
         transfer(ctx, amount)
     }
 }
+
+/// Rejects any of `accounts` unless `token_program` owns it. `Account<TokenAccount>`
+/// already implies this; the explicit check keeps it if the types are ever loosened.
+pub fn check_token_owners(token_program: &Pubkey, accounts: &[AccountInfo]) -> Result<()> {
+    for account in accounts {
+        require_keys_eq!(
+            *account.owner,
+            *token_program,
+            AmmError::InvalidAccountOwner
+        );
+    }
+
+    Ok(())
+}
+
+/// Rejects a swap either of whose transfers the token program would refuse:
+/// a frozen account on either side, an input `source` does not hold, or an
+/// output the vault does not hold. `source` is where the input comes from,
+/// the user's own account or an escrow holding it for them.
+pub fn check_transfers(
+    source: &TokenAccount,
+    vault_in: &TokenAccount,
+    vault_out: &TokenAccount,
+    user_out: &TokenAccount,
+    amount_in: u64,
+    amount_out: u64,
+) -> Result<()> {
+    for account in [source, vault_in, vault_out, user_out] {
+        require!(!account.is_frozen(), AmmError::AccountFrozen);
+    }
+    require!(
+        source.amount >= amount_in && vault_out.amount >= amount_out,
+        AmmError::InsufficientBalance
+    );
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, TokenAccount, Transfer};
+
+use crate::{errors::AmmError, events::FeeTokenPaid, instructions::Swap, state::Config};
+
+#[derive(Accounts)]
+pub struct SwapWithFeeToken<'info> {
+    pub swap: Swap<'info>,
+    #[account(
+        constraint = swap.config.fee_pricing_pool == Some(pricing_config.key())
+            @ AmmError::NoFeeMint,
+    )]
+    pub pricing_config: Account<'info, Config>,
+    #[account(
+        mut,
+        constraint = swap.config.fee_mint == Some(user_fee.mint) @ AmmError::NoFeeMint,
+        constraint = user_fee.owner == swap.user.key() @ AmmError::InvalidAuthority,
+    )]
+    pub user_fee: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = fee_recipient.mint == user_fee.mint @ AmmError::InvalidToken,
+        constraint = swap.config.authority == Some(fee_recipient.owner)
+            @ AmmError::InvalidAuthority,
+    )]
+    pub fee_recipient: Account<'info, TokenAccount>,
+}
+
+impl<'info> SwapWithFeeToken<'info> {
+    /// Swaps like `swap`, but the pool's fee is paid in `fee_mint` instead of
+    /// the traded tokens: the whole `amount_in` is priced fee-free and the user
+    /// transfers the fee's worth of `fee_mint`, see `fee_token_amount`, to the
+    /// pool authority's `fee_recipient`. Either both happen or the swap reverts.
+    ///
+    /// The fee token goes to the authority, not to the pool, so a swap made
+    /// this way pays LPs nothing. Any user can choose this path once `fee_mint`
+    /// is set, so enabling it hands the swap fee from LPs to the authority.
+    ///
+    /// Until the pricing pool's TWAP covers `FEE_PRICE_WINDOW_SECS`, there is
+    /// no fee price, and the fee is charged in the traded tokens and left to
+    /// LPs, exactly like `swap`.
+    pub fn swap_with_fee_token(
+        &mut self,
+        is_x: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        self.swap.config.check_caller(&self.swap.instructions)?;
+
+        // Priced before the swap moves the reserves and the same-slot tracking
+        let Some(fee) = self.fee_token_amount(is_x, amount_in)? else {
+            let fee_discount_bps = self.swap.exemption_discount()?;
+            return self
+                .swap
+                .settle_swap(is_x, amount_in, min_amount_out, fee_discount_bps);
+        };
+
+        self.swap
+            .settle_swap(is_x, amount_in, min_amount_out, 10_000)?;
+
+        if fee != 0 {
+            let cpi_accounts = Transfer {
+                from: self.user_fee.to_account_info(),
+                to: self.fee_recipient.to_account_info(),
+                authority: self.swap.user.to_account_info(),
+            };
+            let ctx = CpiContext::new(self.swap.token_program.to_account_info(), cpi_accounts);
+            transfer(ctx, fee)?;
+        }
+
+        emit!(FeeTokenPaid {
+            config: self.swap.config.key(),
+            user: self.swap.user.key(),
+            fee_mint: self.user_fee.mint,
+            amount: fee,
+        });
+
+        Ok(())
+    }
+
+    /// Fee `swap` would charge, in the fee token and rounded up in favor of
+    /// the pool, see `Config::fee_in_fee_token`, or `None` while there is no
+    /// fee price yet.
+    ///
+    /// The fee is taken as a share of the side of the trade the
+    /// `fee_pricing_pool` pairs the fee token with: of the input, or of the
+    /// curve's fee-free output, so it needs no conversion through this pool's
+    /// own price. It is converted to the fee token at `Config::fee_price`,
+    /// the paired token's average price over at least `FEE_PRICE_WINDOW_SECS`
+    /// of the pricing pool's price accumulators. Swapping the pricing pool
+    /// earlier in the same transaction does not move it, see
+    /// `Config::observe_fee_price`.
+    pub fn fee_token_amount(&mut self, is_x: bool, amount_in: u64) -> Result<Option<u64>> {
+        let fee_mint = self.swap.config.fee_mint.ok_or(AmmError::NoFeeMint)?;
+
+        let pricing = &self.pricing_config;
+        let (paired_mint, cumulative) = match fee_mint {
+            mint if mint == pricing.mint_x => (pricing.mint_y, pricing.price_cumulative_y),
+            mint if mint == pricing.mint_y => (pricing.mint_x, pricing.price_cumulative_x),
+            _ => return err!(AmmError::InvalidToken),
+        };
+        let Some(fee_price) = self
+            .swap
+            .config
+            .observe_fee_price(cumulative, pricing.last_update_ts)
+        else {
+            return Ok(None);
+        };
+
+        let config = &self.swap.config;
+        let (x, y) =
+            config.effective_reserves(self.swap.vault_x.amount, self.swap.vault_y.amount)?;
+        let fee_bps = config.fee_bps(Clock::get()?.slot, x, y, is_x, amount_in);
+
+        // Express the fee in the token the pricing pool pairs the fee token with
+        let fee_paired = match paired_mint {
+            mint if mint == config.mint_in(is_x) => config.swap_fee(amount_in, fee_bps)?,
+            mint if mint == config.mint_in(!is_x) => {
+                let amounts = config.price_swap(x, y, is_x, amount_in, None)?;
+                config.swap_fee(amounts.curve_amount_out, fee_bps)?
+            }
+            _ => return err!(AmmError::InvalidToken),
+        };
+
+        Config::fee_in_fee_token(fee_paired, fee_price).map(Some)
+    }
+}
@@ -0,0 +1,65 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    metadata::{Metadata, MetadataAccount},
+    token::{Mint, TokenAccount},
+};
+
+use crate::{errors::AmmError, instructions::Swap};
+
+#[derive(Accounts)]
+pub struct SwapWithNftDiscount<'info> {
+    pub swap: Swap<'info>,
+    #[account(
+        constraint = nft_mint.supply == 1 && nft_mint.decimals == 0 @ AmmError::InvalidNft,
+    )]
+    pub nft_mint: Account<'info, Mint>,
+    #[account(
+        token::mint = nft_mint,
+        token::authority = swap.user,
+        constraint = nft_account.amount == 1 @ AmmError::InvalidNft,
+    )]
+    pub nft_account: Account<'info, TokenAccount>,
+    #[account(
+        seeds = [b"metadata", metadata_program.key().as_ref(), nft_mint.key().as_ref()],
+        seeds::program = metadata_program.key(),
+        bump,
+    )]
+    pub nft_metadata: Account<'info, MetadataAccount>,
+    pub metadata_program: Program<'info, Metadata>,
+}
+
+impl<'info> SwapWithNftDiscount<'info> {
+    /// Swaps like `swap` for a user holding an NFT from the pool's
+    /// `discount_collection`, with `nft_discount_bps` taken off the fee.
+    ///
+    /// Membership is read from the NFT's token metadata account, the PDA the
+    /// metadata program derives from the mint: its collection must be the
+    /// configured one and verified by the collection authority, so an NFT
+    /// that merely names the collection does not count. The mint must be a
+    /// one-of-one and the user must hold it. An exempt caller inside its
+    /// window still swaps fee-free, see `Swap::exemption_discount`.
+    pub fn swap_with_nft_discount(
+        &mut self,
+        is_x: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        self.swap.config.check_caller(&self.swap.instructions)?;
+
+        let collection = self.swap.config.discount_collection;
+        let verified = self
+            .nft_metadata
+            .collection
+            .as_ref()
+            .is_some_and(|member| member.verified && Some(member.key) == collection);
+        require!(verified, AmmError::InvalidNft);
+
+        let fee_discount_bps = self
+            .swap
+            .exemption_discount()?
+            .max(self.swap.config.nft_discount_bps);
+
+        self.swap
+            .settle_swap(is_x, amount_in, min_amount_out, fee_discount_bps)
+    }
+}
@@ -0,0 +1,269 @@
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        ed25519_program,
+        sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
+    },
+};
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::{transfer, Mint, Token, TokenAccount, Transfer},
+};
+
+use crate::{
+    errors::AmmError,
+    events::{log_swap, SwapEvent},
+    instructions::{check_token_owners, check_transfers},
+    state::{Config, SwapNonce},
+};
+
+/// Prefix of every signed swap message, so the signature cannot be mistaken
+/// for one over a transaction or another program's message.
+pub const SWAP_INTENT_DOMAIN: &[u8] = b"anchor-amm-q4-25:swap";
+
+#[derive(Accounts)]
+pub struct SwapWithSignature<'info> {
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+    /// CHECK: does not sign; authorizes the swap through the ed25519 signature
+    pub user: UncheckedAccount<'info>,
+    pub mint_x: Account<'info, Mint>,
+    pub mint_y: Account<'info, Mint>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+    )]
+    pub vault_x: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+    )]
+    pub vault_y: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+    )]
+    pub user_x: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+    )]
+    pub user_y: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = SwapNonce::DISCRIMINATOR.len() + SwapNonce::INIT_SPACE,
+        seeds = [b"swap_nonce", user.key().as_ref()],
+        bump,
+    )]
+    pub swap_nonce: Account<'info, SwapNonce>,
+    /// CHECK: the instructions sysvar, pinned by address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SwapWithSignature<'info> {
+    /// Executes a swap the user signed off-chain and a relayer submits and
+    /// pays for. The instruction right before this one must be an ed25519
+    /// precompile check of the user's signature over `swap_intent`. The input
+    /// is pulled from the user's token account through a delegation to the
+    /// config PDA, which the user approves beforehand.
+    ///
+    /// Each user has one nonce sequence across all pools; a signed swap must
+    /// carry the next nonce, so it executes at most once and in order.
+    pub fn swap_with_signature(
+        &mut self,
+        is_x: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        nonce: u64,
+        deadline: i64,
+        bumps: SwapWithSignatureBumps,
+    ) -> Result<()> {
+        require!(
+            Clock::get()?.unix_timestamp <= deadline,
+            AmmError::OfferExpired
+        );
+
+        self.swap_nonce.owner = self.user.key();
+        self.swap_nonce.bump = bumps.swap_nonce;
+        require!(nonce == self.swap_nonce.next_nonce, AmmError::NonceReused);
+
+        let message = swap_intent(
+            &self.config.key(),
+            &self.user.key(),
+            is_x,
+            amount_in,
+            min_amount_out,
+            nonce,
+            deadline,
+        );
+        verify_ed25519(&self.instructions, &self.user.key(), &message)?;
+        self.config.check_caller(&self.instructions)?;
+
+        self.swap_nonce.next_nonce = nonce.checked_add(1).ok_or(AmmError::Overflow)?;
+
+        check_token_owners(
+            &self.token_program.key(),
+            &[
+                self.vault_x.to_account_info(),
+                self.vault_y.to_account_info(),
+                self.user_x.to_account_info(),
+                self.user_y.to_account_info(),
+            ],
+        )?;
+        let amounts = self.config.execute_swap(
+            self.vault_x.amount,
+            self.vault_y.amount,
+            self.mint_lp.supply,
+            is_x,
+            amount_in,
+            min_amount_out,
+            0,
+        )?;
+
+        // Input comes in through the delegation, output goes out as in `swap`
+        let (user_in, vault_in, vault_out, user_out) = match is_x {
+            true => (&self.user_x, &self.vault_x, &self.vault_y, &self.user_y),
+            false => (&self.user_y, &self.vault_y, &self.vault_x, &self.user_x),
+        };
+        check_transfers(
+            user_in,
+            vault_in,
+            vault_out,
+            user_out,
+            amount_in,
+            amounts.amount_out,
+        )?;
+        self.transfer_as_config(user_in, vault_in, amount_in)?;
+        self.transfer_as_config(vault_out, user_out, amounts.amount_out)?;
+
+        log_swap(
+            SwapEvent {
+                config: self.config.key(),
+                user: self.user.key(),
+                is_x,
+                amount_in,
+                amount_out: amounts.amount_out,
+                fee: amounts.fee,
+                sequence: self.config.sequence,
+            },
+            self.config.compact_logs,
+        );
+
+        Ok(())
+    }
+
+    /// Transfers with the config PDA as authority, as owner of a vault or as
+    /// delegate of the user's account.
+    fn transfer_as_config(
+        &self,
+        from: &Account<'info, TokenAccount>,
+        to: &Account<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        let cpi_program = self.token_program.to_account_info();
+
+        let cpi_accounts = Transfer {
+            from: from.to_account_info(),
+            to: to.to_account_info(),
+            authority: self.config.to_account_info(),
+        };
+
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"config",
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ]];
+
+        let ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+
+        transfer(ctx, amount)
+    }
+}
+
+/// Message a user signs to authorize a swap: `SWAP_INTENT_DOMAIN`, then the
+/// config and user keys (32 bytes each), the direction (1 byte, 1 if X is
+/// sold), and `amount_in`, `min_amount_out`, `nonce` and `deadline` as
+/// little-endian 8-byte integers.
+pub fn swap_intent(
+    config: &Pubkey,
+    user: &Pubkey,
+    is_x: bool,
+    amount_in: u64,
+    min_amount_out: u64,
+    nonce: u64,
+    deadline: i64,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(SWAP_INTENT_DOMAIN.len() + 97);
+    message.extend_from_slice(SWAP_INTENT_DOMAIN);
+    message.extend_from_slice(config.as_ref());
+    message.extend_from_slice(user.as_ref());
+    message.push(is_x as u8);
+    message.extend_from_slice(&amount_in.to_le_bytes());
+    message.extend_from_slice(&min_amount_out.to_le_bytes());
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message.extend_from_slice(&deadline.to_le_bytes());
+    message
+}
+
+/// Checks that the previous instruction is an ed25519 precompile call that
+/// verified one signature by `signer` over exactly `message`. The precompile
+/// fails the whole transaction on a bad signature, so only what it verified
+/// needs checking here.
+fn verify_ed25519(instructions: &AccountInfo, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    let current = load_current_index_checked(instructions)?;
+    require!(current != 0, AmmError::InvalidSignature);
+    let ix = load_instruction_at_checked(current as usize - 1, instructions)?;
+    require_keys_eq!(
+        ix.program_id,
+        ed25519_program::ID,
+        AmmError::InvalidSignature
+    );
+
+    // One signature: a count byte, a padding byte, then seven u16 offsets
+    let data = &ix.data;
+    require!(data.len() >= 16 && data[0] == 1, AmmError::InvalidSignature);
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+
+    // Every offset must point into the precompile's own data (u16::MAX)
+    require!(
+        read_u16(4) == u16::MAX && read_u16(8) == u16::MAX && read_u16(14) == u16::MAX,
+        AmmError::InvalidSignature
+    );
+
+    let public_key_offset = read_u16(6) as usize;
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+
+    let public_key = data.get(public_key_offset..public_key_offset + 32);
+    let signed_message = data.get(message_offset..message_offset + message_size);
+    require!(
+        public_key == Some(signer.as_ref()) && signed_message == Some(message),
+        AmmError::InvalidSignature
+    );
+
+    Ok(())
+}
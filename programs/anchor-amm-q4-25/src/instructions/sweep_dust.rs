@@ -0,0 +1,120 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Mint, Token, TokenAccount, Transfer};
+
+use crate::{events::DustSwept, math::PRICE_SCALE, state::Config};
+
+#[derive(Accounts)]
+pub struct SweepDust<'info> {
+    pub authority: Signer<'info>,
+    pub mint_x: Account<'info, Mint>,
+    pub mint_y: Account<'info, Mint>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+    )]
+    pub vault_x: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+    )]
+    pub vault_y: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint_x,
+    )]
+    pub treasury_x: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint_y,
+    )]
+    pub treasury_y: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> SweepDust<'info> {
+    /// Moves the whole tokens of rounding dust that swaps have left in the
+    /// vaults to the treasury accounts, keeping the fraction of a token that
+    /// has not accrued yet for a later sweep.
+    ///
+    /// The dust is not LP principal: deposits, withdrawals and swaps are all
+    /// priced against the vault balances less the whole tokens of dust, see
+    /// `Config::lp_reserves`, so sweeping it leaves what each LP token
+    /// redeems for unchanged.
+    pub fn sweep_dust(&mut self) -> Result<()> {
+        self.config.check_authority(self.authority.key())?;
+
+        // Accrued fees are owed to LPs, so dust can never come out of them
+        let (amount_x, amount_y) = self.config.whole_dust();
+        let amount_x = amount_x.min(
+            self.vault_x
+                .amount
+                .saturating_sub(self.config.fees_accrued_x),
+        );
+        let amount_y = amount_y.min(
+            self.vault_y
+                .amount
+                .saturating_sub(self.config.fees_accrued_y),
+        );
+
+        self.config.dust_x -= amount_x as u128 * PRICE_SCALE;
+        self.config.dust_y -= amount_y as u128 * PRICE_SCALE;
+
+        self.config.track_reserves(0, 0, amount_x, amount_y);
+
+        self.withdraw_tokens(true, amount_x)?;
+        self.withdraw_tokens(false, amount_y)?;
+
+        emit!(DustSwept {
+            config: self.config.key(),
+            amount_x,
+            amount_y,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_tokens(&self, is_x: bool, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let (from, to) = match is_x {
+            true => (
+                self.vault_x.to_account_info(),
+                self.treasury_x.to_account_info(),
+            ),
+            false => (
+                self.vault_y.to_account_info(),
+                self.treasury_y.to_account_info(),
+            ),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+
+        let cpi_accounts = Transfer {
+            from,
+            to,
+            authority: self.config.to_account_info(),
+        };
+
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"config",
+            &self.config.seed.to_le_bytes(),
+            &[self.config.config_bump],
+        ]];
+
+        let ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+
+        transfer(ctx, amount)
+    }
+}
@@ -0,0 +1,217 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    curve_adapter,
+    errors::AmmError,
+    events::{ConfigUpdated, PrecisionChanged},
+    state::{Config, MAX_ALLOWED_CALLERS},
+};
+
+/// Config parameters to change in one `update_config` call; `None` leaves a field as is.
+/// A `fee_exempt_caller` of `Pubkey::default()` clears the exempt caller.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct ConfigUpdate {
+    pub quote_mint: Option<Pubkey>,
+    pub fee: Option<u16>,
+    pub same_slot_surcharge_bps: Option<u16>,
+    pub impact_multiplier_bps: Option<u16>,
+    pub max_fee_bps: Option<u16>,
+    pub fee_on_output: Option<bool>,
+    pub min_fee_absolute: Option<u64>,
+    pub max_tvl_x: Option<u64>,
+    pub max_tvl_y: Option<u64>,
+    pub daily_volume_cap: Option<u64>,
+    pub min_ratio: Option<u64>,
+    pub max_ratio: Option<u64>,
+    pub target_ratio: Option<u64>,
+    pub rebalance_strength: Option<u16>,
+    pub accrue_fees: Option<bool>,
+    pub locked: Option<bool>,
+    pub lock_x_to_y: Option<bool>,
+    pub lock_y_to_x: Option<bool>,
+    pub compact_logs: Option<bool>,
+    pub strict_verify: Option<bool>,
+    pub allowed_callers: Option<Vec<Pubkey>>,
+    pub fee_exempt_caller: Option<Pubkey>,
+    pub fee_exempt_until: Option<i64>,
+    pub fee_mint: Option<Pubkey>,
+    pub fee_pricing_pool: Option<Pubkey>,
+    pub discount_collection: Option<Pubkey>,
+    pub nft_discount_bps: Option<u16>,
+}
+
+#[derive(Accounts)]
+pub struct Update<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+impl<'info> Update<'info> {
+    /// Changes the decimal precision swaps are priced on the curve with, to
+    /// any the curve library accepts, see `curve_adapter::check_precision`.
+    /// The new precision can round outputs slightly differently from what was
+    /// quoted before, so the pool must be locked for the change, with no swap
+    /// in flight against quotes at the old precision. Deposits and
+    /// withdrawals are pro rata and keep using
+    /// `curve_adapter::DEFAULT_PRECISION`.
+    pub fn set_precision(&mut self, precision: u8) -> Result<()> {
+        self.config.check_authority(self.authority.key())?;
+        require!(self.config.locked, AmmError::PoolNotLocked);
+        curve_adapter::check_precision(precision)?;
+
+        let old_precision = self.config.precision;
+        self.config.precision = precision;
+
+        emit!(PrecisionChanged {
+            config: self.config.key(),
+            old_precision,
+            new_precision: precision,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_config(&mut self, update: ConfigUpdate) -> Result<()> {
+        self.config.check_authority(self.authority.key())?;
+
+        // Any failed validation reverts the whole update, so fields never change piecemeal
+        if let Some(quote_mint) = update.quote_mint {
+            require!(
+                quote_mint == self.config.mint_x || quote_mint == self.config.mint_y,
+                AmmError::InvalidToken
+            );
+            self.config.quote_mint = Some(quote_mint);
+        }
+        if let Some(fee) = update.fee {
+            require!(fee <= 10_000, AmmError::InvalidFee);
+            // Re-setting the current fee is a no-op and does not restart the interval
+            if fee != self.config.fee {
+                let now = Clock::get()?.unix_timestamp;
+                self.config.check_fee_update(now)?;
+                self.config.fee = fee;
+                self.config.last_fee_update_ts = now;
+            }
+        }
+        if let Some(same_slot_surcharge_bps) = update.same_slot_surcharge_bps {
+            self.config.same_slot_surcharge_bps = same_slot_surcharge_bps;
+        }
+        if let Some(impact_multiplier_bps) = update.impact_multiplier_bps {
+            self.config.impact_multiplier_bps = impact_multiplier_bps;
+        }
+        if let Some(max_fee_bps) = update.max_fee_bps {
+            require!(max_fee_bps <= 10_000, AmmError::InvalidFee);
+            self.config.max_fee_bps = max_fee_bps;
+        }
+        if let Some(fee_on_output) = update.fee_on_output {
+            self.config.fee_on_output = fee_on_output;
+        }
+        if let Some(min_fee_absolute) = update.min_fee_absolute {
+            self.config.min_fee_absolute = min_fee_absolute;
+        }
+        if let Some(max_tvl_x) = update.max_tvl_x {
+            self.config.max_tvl_x = max_tvl_x;
+        }
+        if let Some(max_tvl_y) = update.max_tvl_y {
+            self.config.max_tvl_y = max_tvl_y;
+        }
+        if let Some(daily_volume_cap) = update.daily_volume_cap {
+            self.config.daily_volume_cap = daily_volume_cap;
+        }
+        if let Some(min_ratio) = update.min_ratio {
+            self.config.min_ratio = min_ratio;
+        }
+        if let Some(max_ratio) = update.max_ratio {
+            self.config.max_ratio = max_ratio;
+        }
+        if let Some(target_ratio) = update.target_ratio {
+            self.config.target_ratio = target_ratio;
+        }
+        if let Some(rebalance_strength) = update.rebalance_strength {
+            require!(rebalance_strength <= 10_000, AmmError::InvalidFee);
+            self.config.rebalance_strength = rebalance_strength;
+        }
+        if let Some(accrue_fees) = update.accrue_fees {
+            self.config.accrue_fees = accrue_fees;
+        }
+        if let Some(locked) = update.locked {
+            self.config.locked = locked;
+        }
+        if let Some(lock_x_to_y) = update.lock_x_to_y {
+            self.config.lock_x_to_y = lock_x_to_y;
+        }
+        if let Some(lock_y_to_x) = update.lock_y_to_x {
+            self.config.lock_y_to_x = lock_y_to_x;
+        }
+        if let Some(compact_logs) = update.compact_logs {
+            self.config.compact_logs = compact_logs;
+        }
+        if let Some(strict_verify) = update.strict_verify {
+            self.config.strict_verify = strict_verify;
+        }
+        if let Some(allowed_callers) = &update.allowed_callers {
+            require!(
+                allowed_callers.len() <= MAX_ALLOWED_CALLERS,
+                AmmError::TooManyCallers
+            );
+            self.config.allowed_callers = allowed_callers.clone();
+        }
+        // The default pubkey is not a program that can CPI, so it clears the caller
+        if let Some(fee_exempt_caller) = update.fee_exempt_caller {
+            self.config.fee_exempt_caller = match fee_exempt_caller == Pubkey::default() {
+                true => None,
+                false => Some(fee_exempt_caller),
+            };
+        }
+        if let Some(fee_exempt_until) = update.fee_exempt_until {
+            self.config.fee_exempt_until = fee_exempt_until;
+        }
+        // A fee mint lets every swapper pay the authority instead of LPs, see
+        // `swap_with_fee_token`
+        if let Some(fee_mint) = update.fee_mint {
+            require!(
+                fee_mint != self.config.mint_x && fee_mint != self.config.mint_y,
+                AmmError::InvalidToken
+            );
+            self.config.fee_mint = Some(fee_mint);
+        }
+        if let Some(fee_pricing_pool) = update.fee_pricing_pool {
+            require!(fee_pricing_pool != self.config.key(), AmmError::SamePool);
+            self.config.fee_pricing_pool = Some(fee_pricing_pool);
+        }
+        // The fee price was averaged for the old pair, so it starts over
+        if update.fee_mint.is_some() || update.fee_pricing_pool.is_some() {
+            self.config.fee_price_cumulative = 0;
+            self.config.fee_price_ts = 0;
+            self.config.fee_price = 0;
+        }
+        if let Some(discount_collection) = update.discount_collection {
+            self.config.discount_collection = Some(discount_collection);
+        }
+        if let Some(nft_discount_bps) = update.nft_discount_bps {
+            require!(nft_discount_bps <= 10_000, AmmError::InvalidFee);
+            self.config.nft_discount_bps = nft_discount_bps;
+        }
+
+        // Fields that constrain each other are checked on their final values
+        require!(
+            self.config.fee as u32 + self.config.same_slot_surcharge_bps as u32 <= 10_000,
+            AmmError::InvalidFee
+        );
+        require!(
+            self.config.min_ratio <= self.config.max_ratio,
+            AmmError::InvalidAmount
+        );
+
+        emit!(ConfigUpdated {
+            config: self.config.key(),
+            update,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+use crate::{errors::AmmError, state::Config};
+
+#[derive(Accounts)]
+pub struct SetVirtualReserves<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref()],
+        bump = config.config_bump,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Account<'info, Mint>,
+}
+
+impl<'info> SetVirtualReserves<'info> {
+    /// Sets the virtual reserves, which fix the pool's opening price at
+    /// `virtual_reserve_y / virtual_reserve_x`. Both must be non-zero, so the
+    /// price is defined, or both zero to turn them off. The first deposit has
+    /// to match that price, see `Config::check_opening_price`, so it adds
+    /// liquidity without moving it. Only allowed before any LP tokens exist,
+    /// so the price can never be shifted under existing LPs.
+    pub fn set_virtual_reserves(
+        &mut self,
+        virtual_reserve_x: u64,
+        virtual_reserve_y: u64,
+    ) -> Result<()> {
+        self.config.check_authority(self.authority.key())?;
+        require!(self.mint_lp.supply == 0, AmmError::PoolHasLiquidity);
+        require!(
+            (virtual_reserve_x == 0) == (virtual_reserve_y == 0),
+            AmmError::InvalidAmount
+        );
+
+        self.config.virtual_reserve_x = virtual_reserve_x;
+        self.config.virtual_reserve_y = virtual_reserve_y;
+
+        Ok(())
+    }
+}
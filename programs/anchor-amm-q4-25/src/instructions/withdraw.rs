@@ -3,9 +3,8 @@ use anchor_spl::{
     associated_token::AssociatedToken,
     token::{burn, transfer, Burn, Mint, Token, TokenAccount, Transfer},
 };
-use constant_product_curve::ConstantProduct;
 
-use crate::{errors::AmmError, state::Config};
+use crate::{curve_adapter::withdraw_amounts, errors::AmmError, state::Config};
 
 #[derive(Accounts)]
 pub struct Withdraw<'info> {
@@ -14,6 +13,7 @@ pub struct Withdraw<'info> {
     pub mint_x: Account<'info, Mint>,
     pub mint_y: Account<'info, Mint>,
     #[account(
+        mut,
         has_one = mint_x,
         has_one = mint_y,
         seeds = [b"config", config.seed.to_le_bytes().as_ref()],
@@ -64,25 +64,33 @@ pub struct Withdraw<'info> {
 }
 
 impl<'info> Withdraw<'info> {
+    /// Burns `amount` LP tokens for their share of both vaults, less the
+    /// rounding dust `sweep_dust` is owed, see `Config::lp_reserves`.
+    ///
+    /// Deliberately not blocked by `locked`: a lock pauses swaps and deposits
+    /// while an incident is handled, but LPs must always be able to exit.
     pub fn withdraw(
         &mut self,
         amount: u64, // Amount of LP tokens that the user wants to "burn"
         min_x: u64,  // Minimum amount of token X that the user wants to receive
         min_y: u64,  // Minimum amount of token Y that the user wants to receive
     ) -> Result<()> {
-        require!(self.config.locked == false, AmmError::PoolLocked);
         require!(amount != 0, AmmError::InvalidAmount);
         require!(self.mint_lp.supply != 0, AmmError::NoLiquidityInPool);
 
-        // Calculate the amounts of X and Y to withdraw based on LP tokens being burned
-        let amounts = ConstantProduct::xy_withdraw_amounts_from_l(
-            self.vault_x.amount,
-            self.vault_y.amount,
-            self.mint_lp.supply,
-            amount,
-            6,
-        )
-        .map_err(AmmError::from)?;
+        // Close the oracle interval before the reserves change
+        let (reserve_x, reserve_y) = self
+            .config
+            .effective_reserves(self.vault_x.amount, self.vault_y.amount)?;
+        self.config
+            .update_oracle(reserve_x, reserve_y, Clock::get()?.unix_timestamp);
+
+        // Calculate the amounts of X and Y to withdraw based on LP tokens being burned,
+        // out of what the LPs are owed, rounding dust left out
+        let (lp_x, lp_y) = self
+            .config
+            .lp_reserves(self.vault_x.amount, self.vault_y.amount);
+        let amounts = withdraw_amounts(lp_x, lp_y, self.mint_lp.supply, amount)?;
 
         // Check slippage
         require!(
@@ -90,6 +98,10 @@ impl<'info> Withdraw<'info> {
             AmmError::SlippageExceeded
         );
 
+        self.config
+            .release_fees(amount, self.mint_lp.supply, amounts.x, amounts.y)?;
+        self.config.track_reserves(0, 0, amounts.x, amounts.y);
+
         // Burn LP tokens from user
         self.burn_lp_tokens(amount)?;
 
@@ -100,6 +112,14 @@ impl<'info> Withdraw<'info> {
         self.withdraw_tokens(false, amounts.y)
     }
 
+    /// Burns the caller's entire LP balance as read on-chain, avoiding a stale
+    /// client-side balance.
+    pub fn withdraw_all(&mut self, min_x: u64, min_y: u64) -> Result<()> {
+        let amount = self.user_lp.amount;
+
+        self.withdraw(amount, min_x, min_y)
+    }
+
     pub fn withdraw_tokens(&self, is_x: bool, amount: u64) -> Result<()> {
         let (from, to) = match is_x {
             true => (
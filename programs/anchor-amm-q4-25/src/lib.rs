@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
 
+pub mod curve_adapter;
 mod errors;
+mod events;
 mod instructions;
+mod math;
 mod state;
 
 use instructions::*;
@@ -21,14 +24,177 @@ pub mod anchor_amm_q4_25 {
     }
 
     pub fn deposit(ctx: Context<Deposit>, amount: u64, max_x: u64, max_y: u64) -> Result<()> {
-        ctx.accounts.deposit(amount, max_x, max_y)
+        ctx.accounts.deposit(amount, max_x, max_y, ctx.bumps)
+    }
+
+    pub fn deposit_balanced(
+        ctx: Context<Deposit>,
+        max_x: u64,
+        max_y: u64,
+        min_lp: u64,
+        strict_ratio: bool,
+    ) -> Result<()> {
+        ctx.accounts
+            .deposit_balanced(max_x, max_y, min_lp, strict_ratio, ctx.bumps)
     }
 
     pub fn withdraw(ctx: Context<Withdraw>, amount: u64, max_x: u64, max_y: u64) -> Result<()> {
         ctx.accounts.withdraw(amount, max_x, max_y)
     }
 
+    pub fn withdraw_all(ctx: Context<Withdraw>, min_x: u64, min_y: u64) -> Result<()> {
+        ctx.accounts.withdraw_all(min_x, min_y)
+    }
+
+    pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+        ctx.accounts.close_position()
+    }
+
+    pub fn migrate_liquidity(
+        ctx: Context<MigrateLiquidity>,
+        amount: u64,
+        min_target_lp: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .migrate_liquidity(amount, min_target_lp, ctx.bumps)
+    }
+
     pub fn swap(ctx: Context<Swap>, is_x: bool, amount_in: u64, min_amount_out: u64) -> Result<()> {
         ctx.accounts.swap(is_x, amount_in, min_amount_out)
     }
+
+    pub fn swap_with_stop(
+        ctx: Context<Swap>,
+        is_x: bool,
+        amount_in: u64,
+        stop_price: u64,
+        min_amount_out: u64,
+    ) -> Result<SwapFill> {
+        ctx.accounts
+            .swap_with_stop(is_x, amount_in, stop_price, min_amount_out)
+    }
+
+    pub fn swap_with_fee_token(
+        ctx: Context<SwapWithFeeToken>,
+        is_x: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .swap_with_fee_token(is_x, amount_in, min_amount_out)
+    }
+
+    pub fn swap_with_nft_discount(
+        ctx: Context<SwapWithNftDiscount>,
+        is_x: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .swap_with_nft_discount(is_x, amount_in, min_amount_out)
+    }
+
+    pub fn stage_swap(ctx: Context<StageSwap>, is_x: bool, amount: u64) -> Result<()> {
+        ctx.accounts.stage_swap(is_x, amount, ctx.bumps)
+    }
+
+    pub fn finalize_staged_swap(
+        ctx: Context<FinalizeStagedSwap>,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        ctx.accounts.finalize_staged_swap(min_amount_out)
+    }
+
+    pub fn cancel_staged_swap(ctx: Context<CancelStagedSwap>) -> Result<()> {
+        ctx.accounts.cancel_staged_swap()
+    }
+
+    pub fn swap_with_signature(
+        ctx: Context<SwapWithSignature>,
+        is_x: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        nonce: u64,
+        deadline: i64,
+    ) -> Result<()> {
+        ctx.accounts.swap_with_signature(
+            is_x,
+            amount_in,
+            min_amount_out,
+            nonce,
+            deadline,
+            ctx.bumps,
+        )
+    }
+
+    pub fn poke(ctx: Context<Poke>) -> Result<()> {
+        ctx.accounts.poke()
+    }
+
+    pub fn pool_health(ctx: Context<CheckHealth>) -> Result<PoolHealth> {
+        ctx.accounts.pool_health()
+    }
+
+    pub fn amount_to_reach_price(ctx: Context<Quote>, target_price: u64) -> Result<PriceTarget> {
+        ctx.accounts.amount_to_reach_price(target_price)
+    }
+
+    pub fn config_seeds(ctx: Context<ReadConfig>) -> Result<Vec<Vec<u8>>> {
+        ctx.accounts.config_seeds()
+    }
+
+    pub fn impermanent_loss(ctx: Context<ImpermanentLoss>) -> Result<u64> {
+        ctx.accounts.impermanent_loss()
+    }
+
+    pub fn best_quote<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CompareTiers<'info>>,
+        is_x: bool,
+        amount_in: u64,
+    ) -> Result<BestQuote> {
+        ctx.accounts
+            .best_quote(ctx.remaining_accounts, is_x, amount_in)
+    }
+
+    pub fn quote(ctx: Context<Quote>, is_x: bool, amount_in: u64) -> Result<SwapQuote> {
+        ctx.accounts.quote(is_x, amount_in)
+    }
+
+    pub fn price_after_swap(ctx: Context<Quote>, is_x: bool, amount_in: u64) -> Result<u64> {
+        ctx.accounts.price_after_swap(is_x, amount_in)
+    }
+
+    pub fn quote_normalized(ctx: Context<Quote>, is_x: bool, amount_in: u64) -> Result<u64> {
+        ctx.accounts.quote_normalized(is_x, amount_in)
+    }
+
+    pub fn set_precision(ctx: Context<Update>, precision: u8) -> Result<()> {
+        ctx.accounts.set_precision(precision)
+    }
+
+    pub fn update_config(ctx: Context<Update>, update: ConfigUpdate) -> Result<()> {
+        ctx.accounts.update_config(update)
+    }
+
+    pub fn set_virtual_reserves(
+        ctx: Context<SetVirtualReserves>,
+        virtual_reserve_x: u64,
+        virtual_reserve_y: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .set_virtual_reserves(virtual_reserve_x, virtual_reserve_y)
+    }
+
+    pub fn sweep_dust(ctx: Context<SweepDust>) -> Result<()> {
+        ctx.accounts.sweep_dust()
+    }
+
+    pub fn set_pool_limit(
+        ctx: Context<SetPoolLimit>,
+        initializer: Pubkey,
+        max_pools: u32,
+    ) -> Result<()> {
+        ctx.accounts
+            .set_pool_limit(initializer, max_pools, ctx.bumps)
+    }
 }
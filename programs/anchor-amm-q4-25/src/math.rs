@@ -0,0 +1,341 @@
+/// Fixed-point scale for prices: a price of `PRICE_SCALE` means one unit of
+/// token X is worth one unit of token Y.
+pub const PRICE_SCALE: u128 = 1_000_000_000;
+
+/// Maximum absolute difference (in output token units) tolerated between the
+/// curve library and the in-crate recomputation before a swap is rejected.
+pub const STRICT_VERIFY_TOLERANCE: u64 = 1;
+
+/// Share of either amount, in basis points, a `strict_ratio` balanced deposit
+/// may leave unused before it is rejected as off the pool's ratio.
+pub const DEPOSIT_RATIO_TOLERANCE_BPS: u16 = 50;
+
+/// How far, in basis points, the first deposit into a pool with virtual
+/// reserves may move the opening price they set before it is rejected, see
+/// `Config::check_opening_price`.
+pub const OPENING_PRICE_TOLERANCE_BPS: u16 = 50;
+
+/// Constant-product swap output computed independently of `ConstantProduct`.
+///
+/// The fee is taken from the input (rounded in favor of the pool) and the
+/// output is `reserve_out * net_in / (reserve_in + net_in)`, rounded down.
+/// Returns `None` on overflow or an empty pool.
+pub fn constant_product_out(
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in: u64,
+    fee: u16,
+) -> Option<u64> {
+    let net_in = (amount_in as u128)
+        .checked_mul(10_000u128.checked_sub(fee as u128)?)?
+        .checked_div(10_000)?;
+
+    let numerator = (reserve_out as u128).checked_mul(net_in)?;
+    let denominator = (reserve_in as u128).checked_add(net_in)?;
+
+    u64::try_from(numerator.checked_div(denominator)?).ok()
+}
+
+/// Percentage fee of `fee_bps` on `amount`, rounded up in favor of the pool.
+/// The product is taken in `u128`, so it cannot overflow; the result only
+/// exceeds `amount` for `fee_bps` above 10_000 and then saturates at `u64::MAX`.
+pub fn compute_fee(amount: u64, fee_bps: u16) -> u64 {
+    let fee = (amount as u128 * fee_bps as u128).div_ceil(10_000);
+
+    u64::try_from(fee).unwrap_or(u64::MAX)
+}
+
+/// Price impact of selling `amount_in` into a side holding `reserve_in`, in
+/// basis points rounded down: on a constant-product curve the execution price
+/// is `reserve_in / (reserve_in + amount_in)` of the spot price, so the impact
+/// is `amount_in / (reserve_in + amount_in)`. Zero for an empty trade.
+pub fn price_impact_bps(reserve_in: u64, amount_in: u64) -> u64 {
+    let total = reserve_in as u128 + amount_in as u128;
+    match total {
+        0 => 0,
+        _ => (amount_in as u128 * 10_000 / total) as u64,
+    }
+}
+
+/// Relative move from price `from` to price `to`, in basis points rounded
+/// down and saturating at `u64::MAX`. Zero if `from` is zero, as there is no
+/// earlier price to compare against.
+pub fn price_move_bps(from: u128, to: u128) -> u64 {
+    match from {
+        0 => 0,
+        _ => mul_div(from.abs_diff(to), 10_000, from)
+            .and_then(|bps| u64::try_from(bps).ok())
+            .unwrap_or(u64::MAX),
+    }
+}
+
+/// `amount` less `buffer_bps` of itself, rounded down so the buffer is never
+/// smaller than asked for. A buffer of 10_000 or more leaves nothing.
+pub fn apply_buffer(amount: u64, buffer_bps: u64) -> u64 {
+    let kept = 10_000u128.saturating_sub(buffer_bps as u128);
+
+    (amount as u128 * kept / 10_000) as u64
+}
+
+/// Value a rounded-down swap output leaves behind in the output vault, in
+/// units of `1 / PRICE_SCALE` of a token: the gap between the exact output
+/// `reserve_out * net_in / (reserve_in + net_in)` and `amount_out`, rounded
+/// down. Zero if `amount_out` is at or above the exact output.
+pub fn rounding_dust(
+    reserve_in: u64,
+    reserve_out: u64,
+    net_in: u64,
+    amount_out: u64,
+) -> Option<u128> {
+    let denominator = (reserve_in as u128).checked_add(net_in as u128)?;
+    let exact = (reserve_out as u128).checked_mul(net_in as u128)?;
+    let paid = (amount_out as u128).checked_mul(denominator)?;
+
+    mul_div(exact.saturating_sub(paid), PRICE_SCALE, denominator)
+}
+
+/// Constant-product invariant `x * y`, or `None` if it would not fit in a `u128`.
+///
+/// Reserves are `u64`, and `(2^64 - 1)^2 < 2^128`, so the invariant of any two
+/// reserves a pool can hold always fits; `None` takes wider inputs than a pool
+/// produces. The overflow a swap can actually hit is its input reserve leaving
+/// `u64`, see `Config::check_invariant`.
+pub fn invariant(reserve_x: u128, reserve_y: u128) -> Option<u128> {
+    reserve_x.checked_mul(reserve_y)
+}
+
+/// Spot price of X in units of Y, scaled by `PRICE_SCALE` and rounded down.
+pub fn spot_price(reserve_x: u64, reserve_y: u64) -> Option<u128> {
+    mul_div(reserve_y as u128, PRICE_SCALE, reserve_x as u128)
+}
+
+/// `a * b / c` rounded down, without overflowing on the intermediate product
+/// as long as the result itself and `c * b` fit in a `u128`.
+pub fn mul_div(a: u128, b: u128, c: u128) -> Option<u128> {
+    let whole = a.checked_div(c)?.checked_mul(b)?;
+    let remainder = (a % c).checked_mul(b)?.checked_div(c)?;
+
+    whole.checked_add(remainder)
+}
+
+/// `a * b / c` rounded up, or `None` if `c` is zero or `a * b` does not fit
+/// in a `u128`.
+pub fn mul_div_ceil(a: u128, b: u128, c: u128) -> Option<u128> {
+    match c {
+        0 => None,
+        _ => Some(a.checked_mul(b)?.div_ceil(c)),
+    }
+}
+
+/// Reserve `a` the curve must reach when selling into a side holding
+/// `reserve`, so that the post-swap spot price lands on the one implied by
+/// `q`, counting the fee that stays in the vault on top of the priced input.
+/// When the fee is instead taken from the output, the same equation holds with
+/// `reserve` replaced by the output reserve valued at the target price.
+///
+/// With `f = fee / 10_000`, the input vault ends at `reserve + (a - reserve) / (1 - f)`
+/// and the target is `a * (reserve + (a - reserve) / (1 - f)) = q`, which
+/// rearranges to `a^2 - a * reserve * f - (1 - f) * q = 0`. With no fee this is
+/// just `a = sqrt(q)`.
+pub fn reserve_for_price(reserve: u64, q: u128, fee: u16) -> Option<u128> {
+    let half_fee_term = mul_div(reserve as u128, fee as u128, 20_000)?;
+    let scaled_q = mul_div(q, 10_000u128.checked_sub(fee as u128)?, 10_000)?;
+    let discriminant = half_fee_term
+        .checked_mul(half_fee_term)?
+        .checked_add(scaled_q)?;
+
+    half_fee_term.checked_add(integer_sqrt(discriminant))
+}
+
+/// Impermanent loss of a constant-product position entered at `entry_price`
+/// now that the price is `current_price`, scaled by `PRICE_SCALE`.
+///
+/// With `r = current / entry`, the position is worth `2 * sqrt(r) / (1 + r)` of
+/// simply holding the deposited tokens, so the loss is `1 - 2 * sqrt(r) / (1 + r)`.
+/// It is computed as `1 - 2 * sqrt(entry * current) / (entry + current)`, which
+/// is symmetric in the two prices; the root is rounded down, so the loss is
+/// never understated. Returns `None` if either price is zero or their product
+/// does not fit in a `u128`.
+pub fn impermanent_loss(entry_price: u128, current_price: u128) -> Option<u128> {
+    if entry_price == 0 || current_price == 0 {
+        return None;
+    }
+
+    let geometric_mean = integer_sqrt(entry_price.checked_mul(current_price)?);
+    let value_ratio = mul_div(
+        geometric_mean,
+        PRICE_SCALE.checked_mul(2)?,
+        entry_price.checked_add(current_price)?,
+    )?;
+
+    Some(PRICE_SCALE.saturating_sub(value_ratio))
+}
+
+/// Largest `r` such that `r * r <= n`.
+///
+/// Newton's method started from a power of two at or above the root, so the
+/// estimate decreases monotonically and converges in a handful of iterations.
+pub fn integer_sqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+
+    let bits = 128 - n.leading_zeros();
+    let mut x = 1u128 << bits.div_ceil(2);
+
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_product_out_known_vectors() {
+        assert_eq!(
+            constant_product_out(100_000_000, 100_000_000, 10_000_000, 100),
+            Some(9_008_189)
+        );
+        assert_eq!(constant_product_out(1_000, 1_000_000, 10, 30), Some(8_919));
+        assert_eq!(
+            constant_product_out(100_000_000, 50_000_000, 1_000_000, 0),
+            Some(495_049)
+        );
+        assert_eq!(
+            constant_product_out(u64::MAX, u64::MAX, 1 << 63, 0),
+            Some(6_148_914_691_236_517_205)
+        );
+    }
+
+    #[test]
+    fn constant_product_out_edge_cases() {
+        assert_eq!(constant_product_out(0, 0, 0, 30), None);
+        assert_eq!(constant_product_out(1_000, 1_000, 0, 30), Some(0));
+        assert_eq!(constant_product_out(1_000, 1_000, 1_000, 10_001), None);
+    }
+
+    #[test]
+    fn compute_fee_rounds_up_without_overflow() {
+        assert_eq!(compute_fee(0, 30), 0);
+        assert_eq!(compute_fee(10_000, 30), 30);
+        assert_eq!(compute_fee(10_001, 30), 31);
+        assert_eq!(compute_fee(1, 1), 1);
+        assert_eq!(compute_fee(1_000, 0), 0);
+        // u64::MAX * 30 overflows a u64 but not the u128 intermediate
+        assert_eq!(compute_fee(u64::MAX, 30), 55_340_232_221_128_655);
+        assert_eq!(compute_fee(u64::MAX, 10_000), u64::MAX);
+        assert_eq!(compute_fee(u64::MAX, 10_001), u64::MAX);
+    }
+
+    #[test]
+    fn price_impact_bps_is_share_of_post_trade_reserve() {
+        assert_eq!(price_impact_bps(10_000, 0), 0);
+        assert_eq!(price_impact_bps(0, 0), 0);
+        assert_eq!(price_impact_bps(9_900, 100), 100);
+        assert_eq!(price_impact_bps(1, 3), 7_500);
+        assert_eq!(price_impact_bps(u64::MAX, u64::MAX), 5_000);
+    }
+
+    #[test]
+    fn price_move_bps_is_relative_to_the_earlier_price() {
+        assert_eq!(price_move_bps(0, PRICE_SCALE), 0);
+        assert_eq!(price_move_bps(PRICE_SCALE, PRICE_SCALE), 0);
+        assert_eq!(price_move_bps(10_000, 10_100), 100);
+        assert_eq!(price_move_bps(10_000, 9_900), 100);
+        assert_eq!(price_move_bps(3, 2), 3_333);
+        assert_eq!(price_move_bps(1, u128::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn apply_buffer_rounds_down() {
+        assert_eq!(apply_buffer(10_000, 0), 10_000);
+        assert_eq!(apply_buffer(10_000, 50), 9_950);
+        assert_eq!(apply_buffer(999, 10), 998);
+        assert_eq!(apply_buffer(1_000, 10_000), 0);
+        assert_eq!(apply_buffer(1_000, u64::MAX), 0);
+        assert_eq!(apply_buffer(u64::MAX, 0), u64::MAX);
+    }
+
+    #[test]
+    fn mul_div_ceil_rounds_up() {
+        assert_eq!(mul_div_ceil(10, 3, 5), Some(6));
+        assert_eq!(mul_div_ceil(10, 3, 4), Some(8));
+        assert_eq!(mul_div_ceil(0, 3, 4), Some(0));
+        assert_eq!(mul_div_ceil(1, 1, 0), None);
+        assert_eq!(mul_div_ceil(u128::MAX, 2, 2), None);
+    }
+
+    #[test]
+    fn rounding_dust_is_the_scaled_remainder() {
+        // 1_000 * 3 / 1_003 = 2 remainder 994
+        assert_eq!(rounding_dust(1_000, 1_000, 3, 2), Some(991_026_919));
+        assert_eq!(rounding_dust(1_000, 1_000, 3, 3), Some(0));
+        assert_eq!(rounding_dust(1_000, 2_000, 1_000, 1_000), Some(0));
+        assert_eq!(rounding_dust(0, 1_000, 0, 0), None);
+    }
+
+    #[test]
+    fn mul_div_matches_wide_multiplication() {
+        assert_eq!(mul_div(10, 3, 4), Some(7));
+        assert_eq!(mul_div(u128::MAX, 1_000, 1_000), Some(u128::MAX));
+        assert_eq!(
+            mul_div(u64::MAX as u128 * u64::MAX as u128, PRICE_SCALE, 1 << 64),
+            Some((u64::MAX as u128 - 1) * PRICE_SCALE)
+        );
+        assert_eq!(mul_div(u128::MAX, 2, 1), None);
+        assert_eq!(mul_div(1, 1, 0), None);
+    }
+
+    #[test]
+    fn spot_price_is_scaled_y_over_x() {
+        assert_eq!(spot_price(1_000, 1_000), Some(PRICE_SCALE));
+        assert_eq!(spot_price(4_000, 1_000), Some(PRICE_SCALE / 4));
+        assert_eq!(spot_price(0, 1_000), None);
+    }
+
+    #[test]
+    fn reserve_for_price_reduces_to_sqrt_without_fee() {
+        assert_eq!(reserve_for_price(1_000, 1_000_000, 0), Some(1_000));
+        assert_eq!(reserve_for_price(1_000, 4_000_000, 0), Some(2_000));
+        // 1% fee: a = 50 + isqrt(50^2 + 0.99 * 4_000_000) = 50 + 1_990
+        assert_eq!(reserve_for_price(10_000, 4_000_000, 100), Some(2_040));
+    }
+
+    #[test]
+    fn impermanent_loss_matches_closed_form() {
+        assert_eq!(impermanent_loss(PRICE_SCALE, PRICE_SCALE), Some(0));
+        // r = 4: 1 - 2 * 2 / 5 = 20%, and the same for the inverse move
+        assert_eq!(
+            impermanent_loss(PRICE_SCALE, 4 * PRICE_SCALE),
+            Some(PRICE_SCALE / 5)
+        );
+        assert_eq!(
+            impermanent_loss(4 * PRICE_SCALE, PRICE_SCALE),
+            Some(PRICE_SCALE / 5)
+        );
+        assert_eq!(impermanent_loss(0, PRICE_SCALE), None);
+        assert_eq!(impermanent_loss(u128::MAX, 2), None);
+    }
+
+    #[test]
+    fn integer_sqrt_is_exact_floor() {
+        assert_eq!(integer_sqrt(0), 0);
+        assert_eq!(integer_sqrt(1), 1);
+        assert_eq!(integer_sqrt(2), 1);
+        assert_eq!(integer_sqrt(3), 1);
+        assert_eq!(integer_sqrt(4), 2);
+
+        for r in [10u128, 1_000_000, 1 << 32, u64::MAX as u128] {
+            assert_eq!(integer_sqrt(r * r), r);
+            assert_eq!(integer_sqrt(r * r - 1), r - 1);
+            assert_eq!(integer_sqrt(r * r + 1), r);
+        }
+        assert_eq!(integer_sqrt(u128::MAX), u64::MAX as u128);
+    }
+}
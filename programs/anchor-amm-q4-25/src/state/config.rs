@@ -1,14 +1,1136 @@
-use anchor_lang::prelude::*;
+use std::cmp::Ordering;
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        hash::hashv,
+        instruction::{get_stack_height, TRANSACTION_LEVEL_STACK_HEIGHT},
+        sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
+    },
+};
+
+use crate::{
+    curve_adapter,
+    errors::AmmError,
+    math::{
+        compute_fee, constant_product_out, invariant, mul_div, mul_div_ceil, price_impact_bps,
+        price_move_bps, reserve_for_price, rounding_dust, spot_price, OPENING_PRICE_TOLERANCE_BPS,
+        PRICE_SCALE, STRICT_VERIFY_TOLERANCE,
+    },
+};
+
+/// Capacity of `Config::allowed_callers`, the `max_len` it is allocated with.
+pub const MAX_ALLOWED_CALLERS: usize = 4;
+
+/// Minimum time, in seconds, between two changes of a pool's fee, so LPs are
+/// not exposed to a fee that churns however often the authority wants. The
+/// first change after initialization is not limited, which lets a pool move
+/// off the fee tier it was created at straight away.
+pub const MIN_FEE_UPDATE_INTERVAL: i64 = 60 * 60;
+
+/// Length in seconds of the window `Config::daily_volume_cap` limits the
+/// swap volume of: a day, counted from the first swap after the previous
+/// window ended.
+pub const VOLUME_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// Shortest stretch of the fee pricing pool's price history `Config::fee_price`
+/// is averaged over, so a price held for less than that barely moves it.
+pub const FEE_PRICE_WINDOW_SECS: i64 = 30 * 60;
+
+/// Number of oracle intervals `Config::volatility_bps` averages over: each new
+/// sample moves the average by `1 / VOLATILITY_EMA_PERIOD` of the difference.
+pub const VOLATILITY_EMA_PERIOD: u64 = 8;
 
 #[account]
 #[derive(InitSpace)]
+#[cfg_attr(test, derive(Default))]
 pub struct Config {
-    pub seed: u64,                 // Seed to be able to create different pools / configs
-    pub authority: Option<Pubkey>, // If we want an authority to lock the config account
-    pub mint_x: Pubkey,            // Token X
-    pub mint_y: Pubkey,            // Token Y
-    pub fee: u16,                  // Swap fee in basis points
-    pub locked: bool,              // If the pool is locked
-    pub config_bump: u8,           // Bump seed for the config account
-    pub lp_bump: u8,               // Bump seed for the LP token
+    pub seed: u64,                    // Hash of the sorted mints and the initial fee tier
+    pub authority: Option<Pubkey>,    // If we want an authority to lock the config account
+    pub mint_x: Pubkey,               // Token X
+    pub mint_y: Pubkey,               // Token Y
+    pub quote_mint: Option<Pubkey>,   // Token X or Y that swap outputs are normalized to in quotes
+    pub fee: u16,                     // Swap fee in basis points
+    pub same_slot_surcharge_bps: u16, // Extra fee for a swap in the same slot as the previous one
+    pub impact_multiplier_bps: u16,   // Fee per bp of the swap's price impact, 10_000 = 1x
+    pub max_fee_bps: u16,             // Cap on the fee the impact component can raise it to
+    pub fee_on_output: bool,          // Take the swap fee from the output instead of the input
+    pub min_fee_absolute: u64,        // Min swap fee in the token it is taken from, 0 disables
+    pub max_tvl_x: u64,               // Max token X held after a deposit, u64::MAX disables the cap
+    pub max_tvl_y: u64,               // Max token Y held after a deposit, u64::MAX disables the cap
+    pub daily_volume_cap: u64,        // Max X swapped per volume window, u64::MAX disables the cap
+    pub min_ratio: u64,               // Min Y/X reserve ratio after a swap, scaled by PRICE_SCALE
+    pub max_ratio: u64,               // Max Y/X reserve ratio after a swap, u64::MAX disables
+    pub target_ratio: u64,            // Y/X ratio rebalance mode steers towards, 0 disables it
+    pub rebalance_strength: u16,      // Fee bps added or waived at 100% off `target_ratio`
+    pub virtual_reserve_x: u64,       // Added to vault X when pricing swaps, never withdrawable
+    pub virtual_reserve_y: u64,       // Added to vault Y when pricing swaps, never withdrawable
+    pub tracked_reserve_x: u64,       // Vault X balance as accounted for by pool instructions
+    pub tracked_reserve_y: u64,       // Vault Y balance as accounted for by pool instructions
+    pub accrue_fees: bool,            // Hold swap fees in `fees_accrued_*` instead of the reserves
+    pub fees_accrued_x: u64,          // Swap fees held in vault X outside the reserves
+    pub fees_accrued_y: u64,          // Swap fees held in vault Y outside the reserves
+    pub locked: bool,                 // If swaps and deposits are paused; withdrawals stay open
+    pub lock_x_to_y: bool,            // If swaps selling X for Y are paused
+    pub lock_y_to_x: bool,            // If swaps selling Y for X are paused
+    pub price_cumulative_x: u128,     // Sum of X spot price (in Y, PRICE_SCALE) x seconds, wrapping
+    pub price_cumulative_y: u128,     // Sum of Y spot price (in X, PRICE_SCALE) x seconds, wrapping
+    pub last_update_ts: i64,          // Timestamp the price accumulators were last advanced to
+    pub last_price_x: u128,           // X spot price over the last closed oracle interval
+    pub volatility_bps: u64,          // Average X price move between oracle intervals, in bps
+    pub last_swap_slot: u64,          // Slot of the most recent swap
+    pub sequence: u64,                // Swaps executed so far, the latest `SwapEvent::sequence`
+    pub volume_window_start: i64,     // When the current volume window started
+    pub window_volume: u64,           // X swapped in the current volume window
+    pub last_fee_update_ts: i64,      // When `update_config` last changed the fee, 0 if never
+    pub dust_x: u128,                 // Rounding dust kept in vault X, in 1 / PRICE_SCALE units
+    pub dust_y: u128,                 // Rounding dust kept in vault Y, in 1 / PRICE_SCALE units
+    pub compact_logs: bool,           // Log swaps as packed bytes instead of a `SwapEvent`
+    pub strict_verify: bool,          // Recompute every swap in-crate and compare against the curve
+    pub precision: u8,                // Decimal precision swaps are priced on the curve with
+    #[max_len(MAX_ALLOWED_CALLERS)]
+    pub allowed_callers: Vec<Pubkey>, // Programs allowed to CPI into swaps, empty allows any
+    pub fee_exempt_caller: Option<Pubkey>, // Program whose CPI swaps pay no fee until the expiry
+    pub fee_exempt_until: i64,        // Unix time the fee exemption ends, exclusive
+    pub fee_mint: Option<Pubkey>,     // Token `swap_with_fee_token` pays fees in, to the authority
+    pub fee_pricing_pool: Option<Pubkey>, // Pool pairing `fee_mint` with X or Y that prices it
+    pub fee_price_cumulative: u128,   // Pricing pool's paired-token accumulator when observed
+    pub fee_price_ts: i64,            // Pricing pool's `last_update_ts` then, 0 if never observed
+    pub fee_price: u128,              // Paired token's average price in `fee_mint`, 0 if none yet
+    pub discount_collection: Option<Pubkey>, // NFT collection whose holders get `nft_discount_bps`
+    pub nft_discount_bps: u16,        // Share of the fee waived for discount NFT holders
+    pub config_bump: u8,              // Bump seed for the config account
+    pub lp_bump: u8,                  // Bump seed for the LP token
+}
+
+/// Swap needed to move the pool's spot price to a target, returned by
+/// `amount_to_reach_price` through `set_return_data`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PriceTarget {
+    pub is_x: bool,     // Direction to swap in, same meaning as in `swap`
+    pub amount_in: u64, // Input amount including the fee, 0 if already at the target
+}
+
+/// A swap priced against a snapshot of the vault balances.
+pub struct SwapAmounts {
+    pub net_amount_in: u64,    // Input left for the curve after any input fee
+    pub curve_amount_out: u64, // Output of the curve before any output fee
+    pub amount_out: u64,       // Output sent to the user
+    pub fee: u64,              // Fee kept in the vault, in the output token with `fee_on_output`
+}
+
+/// A swap that passed every check in `Config::check_swap`, with what
+/// `Config::execute_swap` needs to apply it.
+pub struct CheckedSwap {
+    pub amounts: SwapAmounts,
+    pub reserve_x: u64,           // Effective reserves the swap was priced against
+    pub reserve_y: u64,           // Effective reserves the swap was priced against
+    pub reserve_in_added: u64,    // See `Config::reserve_deltas`
+    pub reserve_out_removed: u64, // See `Config::reserve_deltas`
+    pub window_start: i64,        // Volume window once the swap is counted
+    pub window_volume: u64,       // Volume window once the swap is counted
+}
+
+impl Config {
+    /// Canonical `seed` for a pool over `mint_x` and `mint_y` at fee tier `fee`:
+    /// the first 8 bytes, little endian, of `sha256(low_mint || high_mint || fee)`.
+    /// The mints are sorted first, so both orderings of a pair map to the same
+    /// config PDA and a pair and fee tier can only ever back one pool.
+    pub fn canonical_seed(mint_x: &Pubkey, mint_y: &Pubkey, fee: u16) -> u64 {
+        let (low, high) = if mint_x <= mint_y {
+            (mint_x, mint_y)
+        } else {
+            (mint_y, mint_x)
+        };
+        let digest = hashv(&[low.as_ref(), high.as_ref(), &fee.to_le_bytes()]);
+
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&digest.to_bytes()[..8]);
+        u64::from_le_bytes(seed)
+    }
+
+    /// Seeds the program signs as this config with, `[b"config", seed as
+    /// little endian bytes, [config_bump]]`, the same the account is derived
+    /// from, so `create_program_address` over them yields its address.
+    pub fn signer_seeds(&self) -> Vec<Vec<u8>> {
+        vec![
+            b"config".to_vec(),
+            self.seed.to_le_bytes().to_vec(),
+            vec![self.config_bump],
+        ]
+    }
+
+    /// Mint a swap selling X (`is_x`) or Y takes its input in.
+    pub fn mint_in(&self, is_x: bool) -> Pubkey {
+        match is_x {
+            true => self.mint_x,
+            false => self.mint_y,
+        }
+    }
+
+    /// Whole tokens of rounding dust tracked in each vault, the part of
+    /// `dust_x` and `dust_y` that `sweep_dust` can move to the treasury.
+    pub fn whole_dust(&self) -> (u64, u64) {
+        let whole = |dust: u128| u64::try_from(dust / PRICE_SCALE).unwrap_or(u64::MAX);
+        (whole(self.dust_x), whole(self.dust_y))
+    }
+
+    /// Vault balances less the whole tokens of rounding dust in them: what
+    /// the LPs are owed, and what deposits and withdrawals are priced
+    /// against pro rata. The dust belongs to no LP; it is left for
+    /// `sweep_dust`.
+    pub fn lp_reserves(&self, vault_x: u64, vault_y: u64) -> (u64, u64) {
+        let (dust_x, dust_y) = self.whole_dust();
+        (
+            vault_x.saturating_sub(dust_x),
+            vault_y.saturating_sub(dust_y),
+        )
+    }
+
+    /// LP reserves, see `lp_reserves`, less the swap fees accrued in them:
+    /// the part of each vault that is tradeable liquidity.
+    pub fn principal_reserves(&self, vault_x: u64, vault_y: u64) -> (u64, u64) {
+        let (lp_x, lp_y) = self.lp_reserves(vault_x, vault_y);
+        (
+            lp_x.saturating_sub(self.fees_accrued_x),
+            lp_y.saturating_sub(self.fees_accrued_y),
+        )
+    }
+
+    /// Reserves swaps are priced against: the principal reserves, see
+    /// `principal_reserves`, plus the virtual reserves. Virtual reserves let
+    /// a bootstrap pool (LBP style) open at a chosen price without matching
+    /// real liquidity; they move the curve but are never paid out, so selling
+    /// into the virtual side only works once the vault holds real tokens to
+    /// pay with.
+    pub fn effective_reserves(&self, vault_x: u64, vault_y: u64) -> Result<(u64, u64)> {
+        let (vault_x, vault_y) = self.principal_reserves(vault_x, vault_y);
+        let reserve_x = vault_x
+            .checked_add(self.virtual_reserve_x)
+            .ok_or(AmmError::ReservesTooLarge)?;
+        let reserve_y = vault_y
+            .checked_add(self.virtual_reserve_y)
+            .ok_or(AmmError::ReservesTooLarge)?;
+
+        Ok((reserve_x, reserve_y))
+    }
+
+    /// Rejects a first deposit that moves the opening price set by the virtual
+    /// reserves: `price_after`, the spot price after the deposit, must be
+    /// within `OPENING_PRICE_TOLERANCE_BPS` of the price of `reserve_x` and
+    /// `reserve_y`, the effective reserves before it. No-op without virtual
+    /// reserves, where the first deposit sets the price.
+    pub fn check_opening_price(
+        &self,
+        reserve_x: u64,
+        reserve_y: u64,
+        price_after: u128,
+    ) -> Result<()> {
+        if self.virtual_reserve_x == 0 || self.virtual_reserve_y == 0 {
+            return Ok(());
+        }
+
+        let opening_price = spot_price(reserve_x, reserve_y).ok_or(AmmError::NoLiquidityInPool)?;
+        require!(
+            price_move_bps(opening_price, price_after) <= OPENING_PRICE_TOLERANCE_BPS as u64,
+            AmmError::UnbalancedDeposit
+        );
+
+        Ok(())
+    }
+
+    /// Records tokens the pool's own instructions move into (`x_in`, `y_in`) and
+    /// out of (`x_out`, `y_out`) the vaults. Direct transfers to a vault bypass
+    /// this, which is what `pool_health` detects. Saturating, since a donation
+    /// can let a vault pay out more than was ever tracked.
+    pub fn track_reserves(&mut self, x_in: u64, y_in: u64, x_out: u64, y_out: u64) {
+        self.tracked_reserve_x = self
+            .tracked_reserve_x
+            .saturating_add(x_in)
+            .saturating_sub(x_out);
+        self.tracked_reserve_y = self
+            .tracked_reserve_y
+            .saturating_add(y_in)
+            .saturating_sub(y_out);
+    }
+
+    /// Takes the accrued fees paid out with a withdrawal off the accumulators:
+    /// burning `lp` of `lp_supply` LP tokens pays that share of each vault,
+    /// `x` and `y`, and with it the same share of the fees accrued there.
+    /// Accrued fees belong to the LPs all along; they are only kept out of
+    /// the price until paid out.
+    pub fn release_fees(&mut self, lp: u64, lp_supply: u64, x: u64, y: u64) -> Result<()> {
+        let share = |accrued: u64, paid: u64| -> Result<u64> {
+            let fees = mul_div(accrued as u128, lp as u128, lp_supply as u128)
+                .ok_or(AmmError::Overflow)?;
+            Ok((fees.min(accrued as u128) as u64).min(paid))
+        };
+
+        self.fees_accrued_x -= share(self.fees_accrued_x, x)?;
+        self.fees_accrued_y -= share(self.fees_accrued_y, y)?;
+
+        Ok(())
+    }
+
+    /// Advances the TWAP accumulators to `now` using the reserves that held
+    /// since the last update, Uniswap v2 style. The sums wrap on overflow, so
+    /// consumers take `wrapping_sub` of two observations and divide by the
+    /// elapsed time. No-op within the same timestamp; an empty pool adds nothing.
+    ///
+    /// The closed interval's X price is also compared with the previous
+    /// interval's, and the move folded into `volatility_bps`.
+    pub fn update_oracle(&mut self, reserve_x: u64, reserve_y: u64, now: i64) {
+        let elapsed = now.saturating_sub(self.last_update_ts);
+        if elapsed <= 0 {
+            return;
+        }
+
+        if let (Some(price_x), Some(price_y)) = (
+            spot_price(reserve_x, reserve_y),
+            spot_price(reserve_y, reserve_x),
+        ) {
+            self.price_cumulative_x = self
+                .price_cumulative_x
+                .wrapping_add(price_x.wrapping_mul(elapsed as u128));
+            self.price_cumulative_y = self
+                .price_cumulative_y
+                .wrapping_add(price_y.wrapping_mul(elapsed as u128));
+
+            // Each closed interval is one volatility sample, weighted 1 / VOLATILITY_EMA_PERIOD
+            if self.last_price_x != 0 {
+                let sample = price_move_bps(self.last_price_x, price_x) as u128;
+                let period = VOLATILITY_EMA_PERIOD as u128;
+                let average = (self.volatility_bps as u128 * (period - 1) + sample) / period;
+                self.volatility_bps = average as u64;
+            }
+            self.last_price_x = price_x;
+        }
+
+        self.last_update_ts = now;
+    }
+
+    /// Volume window after a swap moving `volume_x` of X at `now`: its start
+    /// and the X swapped in it, this swap included. A window lasts
+    /// `VOLUME_WINDOW_SECS`; the first swap after that opens a new one at
+    /// `now`. Volume is counted in X for both directions, the input of a swap
+    /// selling X and the output of one selling Y, so the cap has a single
+    /// unit. Fails with `VolumeCapExceeded` past `daily_volume_cap`.
+    pub fn window_volume_after(&self, volume_x: u64, now: i64) -> Result<(i64, u64)> {
+        let window_end = self.volume_window_start.saturating_add(VOLUME_WINDOW_SECS);
+        let (start, volume) = match now >= window_end {
+            true => (now, 0),
+            false => (self.volume_window_start, self.window_volume),
+        };
+
+        let volume = volume.saturating_add(volume_x);
+        require!(
+            self.daily_volume_cap == u64::MAX || volume <= self.daily_volume_cap,
+            AmmError::VolumeCapExceeded
+        );
+
+        Ok((start, volume))
+    }
+
+    /// Rejects a fee change at `now` with `FeeUpdateTooFrequent` until
+    /// `MIN_FEE_UPDATE_INTERVAL` has passed since the last one. The first
+    /// change, with `last_fee_update_ts` still 0, is always allowed.
+    pub fn check_fee_update(&self, now: i64) -> Result<()> {
+        let earliest = self
+            .last_fee_update_ts
+            .saturating_add(MIN_FEE_UPDATE_INTERVAL);
+        require!(
+            self.last_fee_update_ts == 0 || now >= earliest,
+            AmmError::FeeUpdateTooFrequent
+        );
+
+        Ok(())
+    }
+
+    /// Folds an observation of the `fee_pricing_pool`'s price accumulator for
+    /// the token it pairs `fee_mint` with, `cumulative` as of its
+    /// `last_update_ts` `observed_ts`, into `fee_price`, and returns that
+    /// price, or `None` before the first window has closed. A new average is
+    /// only taken once the accumulator has advanced `FEE_PRICE_WINDOW_SECS`
+    /// past the stored observation; until then the previous one holds.
+    ///
+    /// Both values are the pricing pool's stored state, which its swaps only
+    /// advance with the reserves that held before them, so swapping it earlier
+    /// in the same transaction leaves the price alone. Tokens sent straight to
+    /// its vaults do count once its oracle is next updated, for all the time
+    /// since the previous update, so the pricing pool should be one that is
+    /// swapped against or `poke`d often.
+    pub fn observe_fee_price(&mut self, cumulative: u128, observed_ts: i64) -> Option<u128> {
+        match self.fee_price_ts {
+            0 => {}
+            start if observed_ts.saturating_sub(start) >= FEE_PRICE_WINDOW_SECS => {
+                let elapsed = (observed_ts - start) as u128;
+                self.fee_price = cumulative.wrapping_sub(self.fee_price_cumulative) / elapsed;
+            }
+            _ => return (self.fee_price != 0).then_some(self.fee_price),
+        }
+        self.fee_price_cumulative = cumulative;
+        self.fee_price_ts = observed_ts;
+
+        (self.fee_price != 0).then_some(self.fee_price)
+    }
+
+    /// Converts `fee_paired`, a fee in the token the `fee_pricing_pool` pairs
+    /// `fee_mint` with, to `fee_mint` at `fee_price`, the paired token's price
+    /// in the fee token scaled by `PRICE_SCALE`. Rounded up in favor of the pool.
+    pub fn fee_in_fee_token(fee_paired: u64, fee_price: u128) -> Result<u64> {
+        let fee_token =
+            mul_div_ceil(fee_paired as u128, fee_price, PRICE_SCALE).ok_or(AmmError::Overflow)?;
+
+        u64::try_from(fee_token).map_err(|_| error!(AmmError::Overflow))
+    }
+
+    /// Rejects post-swap reserves whose Y/X ratio, scaled by `PRICE_SCALE` like
+    /// the spot price, falls outside `[min_ratio, max_ratio]`. This bounds the
+    /// price range the pool will quote, e.g. for pegged or wrapped-asset pairs.
+    pub fn check_ratio(&self, reserve_x: u64, reserve_y: u64) -> Result<()> {
+        let ratio = spot_price(reserve_x, reserve_y).ok_or(AmmError::RatioOutOfBounds)?;
+
+        require!(ratio >= self.min_ratio as u128, AmmError::RatioOutOfBounds);
+        require!(
+            self.max_ratio == u64::MAX || ratio <= self.max_ratio as u128,
+            AmmError::RatioOutOfBounds
+        );
+
+        Ok(())
+    }
+
+    /// Fee in basis points for a swap selling `amount_in` of X (`is_x`) or Y
+    /// into reserves `reserve_x`/`reserve_y`, landing in `slot`. The extras
+    /// stay in the vault for LPs like the base fee:
+    /// - A swap in the same slot as the previous one pays
+    ///   `same_slot_surcharge_bps` on top, making same-slot sandwiches and
+    ///   back-runs costlier.
+    /// - With `target_ratio` and `rebalance_strength` set, the fee moves by
+    ///   `min(|spot - target| / target, 1) * rebalance_strength` bps, where
+    ///   `spot` is the Y/X reserve ratio scaled by `PRICE_SCALE` like
+    ///   `target_ratio`. A swap towards the target pays that much less, down
+    ///   to zero, and one away from it that much more, up to `max_fee_bps`.
+    ///   Selling X lowers the ratio and selling Y raises it.
+    /// - With `impact_multiplier_bps` set, a swap also pays its own price
+    ///   impact times the multiplier, so large trades compensate LPs for the
+    ///   price move they cause. This part only raises the fee up to `max_fee_bps`.
+    pub fn fee_bps(
+        &self,
+        slot: u64,
+        reserve_x: u64,
+        reserve_y: u64,
+        is_x: bool,
+        amount_in: u64,
+    ) -> u16 {
+        let reserve_in = match is_x {
+            true => reserve_x,
+            false => reserve_y,
+        };
+
+        let mut fee = self.fee;
+        if slot == self.last_swap_slot {
+            fee = fee.saturating_add(self.same_slot_surcharge_bps);
+        }
+
+        let rebalance = self.rebalance_adjustment(reserve_x, reserve_y, is_x);
+        if let Some((restores, adjustment)) = rebalance {
+            fee = match restores {
+                true => fee.saturating_sub(adjustment),
+                false => fee.max(fee.saturating_add(adjustment).min(self.max_fee_bps)),
+            };
+        }
+
+        if self.impact_multiplier_bps != 0 {
+            let impact_fee = price_impact_bps(reserve_in, amount_in) as u128
+                * self.impact_multiplier_bps as u128
+                / 10_000;
+            let headroom = self.max_fee_bps.saturating_sub(fee);
+            fee += impact_fee.min(headroom as u128) as u16;
+        }
+
+        fee
+    }
+
+    /// Rebalance mode's part of `fee_bps`: whether the swap moves the ratio
+    /// towards `target_ratio`, and the adjustment in basis points. `None`
+    /// while the mode is off or the pool is empty.
+    fn rebalance_adjustment(
+        &self,
+        reserve_x: u64,
+        reserve_y: u64,
+        is_x: bool,
+    ) -> Option<(bool, u16)> {
+        if self.target_ratio == 0 || self.rebalance_strength == 0 {
+            return None;
+        }
+
+        let spot = spot_price(reserve_x, reserve_y)?;
+        let target = self.target_ratio as u128;
+        let deviation_bps = price_move_bps(target, spot).min(10_000);
+        let adjustment = deviation_bps as u128 * self.rebalance_strength as u128 / 10_000;
+
+        let restores = match is_x {
+            true => spot > target,
+            false => spot < target,
+        };
+
+        Some((restores, adjustment as u16))
+    }
+
+    /// Fee charged on `amount`, in the same token: the percentage fee (rounded
+    /// up), or `min_fee_absolute` if that is larger. The floor makes tiny swaps
+    /// uneconomical by design, discouraging dust-producing spam.
+    pub fn swap_fee(&self, amount: u64, fee_bps: u16) -> Result<u64> {
+        Ok(compute_fee(amount, fee_bps).max(self.min_fee_absolute))
+    }
+
+    /// Smallest input that still leaves `net_amount_in` after `swap_fee`.
+    pub fn gross_amount_in(&self, net_amount_in: u64, fee_bps: u16) -> Result<u64> {
+        require!(fee_bps < 10_000, AmmError::InvalidFee);
+
+        let with_percentage_fee = (net_amount_in as u128)
+            .checked_mul(10_000)
+            .ok_or(AmmError::Overflow)?
+            .div_ceil(10_000 - fee_bps as u128);
+        let with_percentage_fee =
+            u64::try_from(with_percentage_fee).map_err(|_| AmmError::Overflow)?;
+        let with_fee_floor = net_amount_in
+            .checked_add(self.min_fee_absolute)
+            .ok_or(AmmError::Overflow)?;
+
+        Ok(with_percentage_fee.max(with_fee_floor))
+    }
+
+    /// Prices a swap of `amount_in` against reserves `reserve_x`/`reserve_y`
+    /// exactly as `Swap::swap` executes it, without slippage checks.
+    ///
+    /// By default the fee comes off the input and only the rest is priced. With
+    /// `fee_on_output` the whole input is priced and the fee comes off the
+    /// curve's output instead, staying in the output vault. For the same
+    /// percentage this pays out slightly less, since the fee is charged on an
+    /// output the full input already moved the price for. A `fee_bps` of
+    /// `None` prices a swap that pays no fee, see `execute_swap`.
+    pub fn price_swap(
+        &self,
+        reserve_x: u64,
+        reserve_y: u64,
+        is_x: bool,
+        amount_in: u64,
+        fee_bps: Option<u16>,
+    ) -> Result<SwapAmounts> {
+        // A fee-exempt swap pays neither the percentage fee nor the floor
+        let fee = |amount| match fee_bps {
+            Some(fee_bps) => self.swap_fee(amount, fee_bps),
+            None => Ok(0),
+        };
+
+        // Take the fee here rather than in the curve so the absolute floor can apply
+        let input_fee = match self.fee_on_output {
+            true => 0,
+            false => fee(amount_in)?,
+        };
+        require!(amount_in > input_fee, AmmError::AmountTooSmall);
+        let net_amount_in = amount_in - input_fee;
+
+        // Slippage is checked by the caller against the final output
+        let outcome =
+            curve_adapter::swap(reserve_x, reserve_y, is_x, net_amount_in, self.precision)?;
+        // The curve runs fee-free; a fee of its own would be charged on top of ours
+        require!(outcome.fee_paid == 0, AmmError::CurveMismatch);
+        let curve_amount_out = outcome.amount_out;
+
+        let output_fee = match self.fee_on_output {
+            true => fee(curve_amount_out)?,
+            false => 0,
+        };
+        require!(curve_amount_out > output_fee, AmmError::AmountTooSmall);
+
+        Ok(SwapAmounts {
+            net_amount_in,
+            curve_amount_out,
+            amount_out: curve_amount_out - output_fee,
+            fee: input_fee + output_fee,
+        })
+    }
+
+    /// What a swap of `amount_in` priced as `amounts` adds to the input
+    /// reserve and removes from the output reserve. The whole input and the
+    /// curve's whole output move the reserves, except that with `accrue_fees`
+    /// the fee is set aside in its vault instead of staying in the reserves.
+    pub fn reserve_deltas(&self, amount_in: u64, amounts: &SwapAmounts) -> (u64, u64) {
+        match (self.accrue_fees, self.fee_on_output) {
+            (false, _) => (amount_in, amounts.amount_out),
+            (true, false) => (amounts.net_amount_in, amounts.amount_out),
+            (true, true) => (amount_in, amounts.curve_amount_out),
+        }
+    }
+
+    /// Input needed to push the spot price of X (in Y, scaled by `PRICE_SCALE`)
+    /// to `target_price` against the given vault balances. Holding `k = x * y`
+    /// fixed, the target reserves are `x' = sqrt(k / p)` and `y' = sqrt(k * p)`,
+    /// adjusted by `reserve_for_price` for the fee left in the vault, and the
+    /// input is that reserve change, grossed up for the fee unless it is taken
+    /// from the output. A lower target sells X, a higher one sells Y. When the
+    /// absolute fee floor binds, the price misses the target slightly.
+    pub fn price_target(
+        &self,
+        vault_x: u64,
+        vault_y: u64,
+        target_price: u64,
+    ) -> Result<PriceTarget> {
+        require!(target_price != 0, AmmError::InvalidAmount);
+
+        let (x, y) = self.effective_reserves(vault_x, vault_y)?;
+        require!(x != 0 && y != 0, AmmError::NoLiquidityInPool);
+
+        let spot = spot_price(x, y).ok_or(AmmError::Overflow)?;
+        let k = invariant(x as u128, y as u128).ok_or(AmmError::ReservesTooLarge)?;
+        let target = target_price as u128;
+
+        let (is_x, reserve_in, squared_reserve_in) = match target.cmp(&spot) {
+            Ordering::Equal => {
+                return Ok(PriceTarget {
+                    is_x: false,
+                    amount_in: 0,
+                })
+            }
+            // X gets cheaper, so X has to be sold into the pool
+            Ordering::Less => (true, x, mul_div(k, PRICE_SCALE, target)),
+            // X gets more expensive, so Y has to be sold into the pool
+            Ordering::Greater => (false, y, mul_div(k, target, PRICE_SCALE)),
+        };
+        let squared_reserve_in = squared_reserve_in.ok_or(AmmError::ReservesTooLarge)?;
+
+        // The fee left in the output vault weighs on the price in input units
+        let fee_reserve = match (self.fee_on_output, is_x) {
+            (false, _) => Some(reserve_in as u128),
+            (true, true) => mul_div(y as u128, PRICE_SCALE, target),
+            (true, false) => mul_div(x as u128, target, PRICE_SCALE),
+        };
+        let fee_reserve = fee_reserve
+            .and_then(|reserve| u64::try_from(reserve).ok())
+            .ok_or(AmmError::ReservesTooLarge)?;
+
+        // The impact fee depends on the amount itself, so solve once at the fee
+        // an empty trade pays and again at the fee that first answer would pay
+        let slot = Clock::get()?.slot;
+        let fee_bps = self.fee_bps(slot, x, y, is_x, 0);
+        let mut amount_in =
+            self.input_for_price(reserve_in, fee_reserve, squared_reserve_in, fee_bps)?;
+        if self.impact_multiplier_bps != 0 {
+            let fee_bps = self.fee_bps(slot, x, y, is_x, amount_in);
+            amount_in =
+                self.input_for_price(reserve_in, fee_reserve, squared_reserve_in, fee_bps)?;
+        }
+
+        Ok(PriceTarget { is_x, amount_in })
+    }
+
+    /// Input, fee included, that takes `reserve_in` to the root of
+    /// `reserve_for_price` at a fee of `fee_bps`. With `accrue_fees` no fee
+    /// stays in the reserves to weigh on the price, so only the gross-up uses it.
+    fn input_for_price(
+        &self,
+        reserve_in: u64,
+        fee_reserve: u64,
+        squared_reserve_in: u128,
+        fee_bps: u16,
+    ) -> Result<u64> {
+        let retained_fee_bps = match self.accrue_fees {
+            true => 0,
+            false => fee_bps,
+        };
+        let target_reserve_in =
+            reserve_for_price(fee_reserve, squared_reserve_in, retained_fee_bps)
+                .ok_or(AmmError::ReservesTooLarge)?;
+        let target_reserve_in =
+            u64::try_from(target_reserve_in).map_err(|_| AmmError::ReservesTooLarge)?;
+
+        // Rounding can leave the target within one unit of the current reserve
+        let net_amount_in = target_reserve_in.saturating_sub(reserve_in);
+        match net_amount_in == 0 || self.fee_on_output {
+            true => Ok(net_amount_in),
+            false => self.gross_amount_in(net_amount_in, fee_bps),
+        }
+    }
+
+    /// Runs every check a swap is subject to and prices it against the vault
+    /// balances at `clock`, without changing the pool. `execute_swap` applies
+    /// the result; `best_quote` uses it to skip pools a swap would be
+    /// rejected on. `fee_discount_bps` comes off the fee's basis points,
+    /// rounded in favor of the pool; at 10_000 the swap is charged no fee at
+    /// all, not even `min_fee_absolute`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn check_swap(
+        &self,
+        vault_x: u64,
+        vault_y: u64,
+        lp_supply: u64,
+        is_x: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        fee_discount_bps: u16,
+        clock: &Clock,
+    ) -> Result<CheckedSwap> {
+        require!(self.locked == false, AmmError::PoolLocked);
+        let direction_locked = match is_x {
+            true => self.lock_x_to_y,
+            false => self.lock_y_to_x,
+        };
+        require!(!direction_locked, AmmError::DirectionLocked);
+        require!(amount_in != 0, AmmError::InvalidAmount);
+
+        // Tokens donated to a pool with no LPs are not liquidity; a deposit must come first
+        require!(lp_supply != 0, AmmError::NoLiquidityInPool);
+
+        // Price the swap against real plus virtual reserves
+        let (reserve_x, reserve_y) = self.effective_reserves(vault_x, vault_y)?;
+        let (principal_x, principal_y) = self.principal_reserves(vault_x, vault_y);
+        let (reserve_in, reserve_out, principal_out) = match is_x {
+            true => (reserve_x, reserve_y, principal_y),
+            false => (reserve_y, reserve_x, principal_x),
+        };
+        let fee_bps = match fee_discount_bps {
+            10_000.. => None,
+            discount => {
+                let fee_bps = self.fee_bps(clock.slot, reserve_x, reserve_y, is_x, amount_in);
+                Some(fee_bps - (fee_bps as u32 * discount as u32 / 10_000) as u16)
+            }
+        };
+        let amounts = self.price_swap(reserve_x, reserve_y, is_x, amount_in, fee_bps)?;
+
+        require!(amounts.amount_out != 0, AmmError::InvalidAmount);
+        // Inclusive on purpose: an exact quote passed as the minimum must succeed
+        require!(
+            amounts.amount_out >= min_amount_out,
+            AmmError::SlippageExceeded
+        );
+
+        let (reserve_in_added, reserve_out_removed) = self.reserve_deltas(amount_in, &amounts);
+
+        // Virtual reserves only shape the price and accrued fees are set aside,
+        // so the output must come out of the principal
+        require!(
+            reserve_out_removed <= principal_out,
+            AmmError::InsufficientBalance
+        );
+
+        // Post-swap reserves must fit and must not decrease the invariant
+        Self::check_invariant(
+            reserve_in,
+            reserve_out,
+            reserve_in_added,
+            reserve_out_removed,
+        )?;
+
+        // Keep the pool inside its configured price band, reserves already checked above
+        let (reserve_x_after, reserve_y_after) = match is_x {
+            true => (
+                reserve_x + reserve_in_added,
+                reserve_y - reserve_out_removed,
+            ),
+            false => (
+                reserve_x - reserve_out_removed,
+                reserve_y + reserve_in_added,
+            ),
+        };
+        self.check_ratio(reserve_x_after, reserve_y_after)?;
+
+        let volume_x = match is_x {
+            true => amount_in,
+            false => amounts.amount_out,
+        };
+        let (window_start, window_volume) =
+            self.window_volume_after(volume_x, clock.unix_timestamp)?;
+
+        // Cross-check the curve library before any funds move
+        if self.strict_verify {
+            self.verify_output(
+                reserve_in,
+                reserve_out,
+                amounts.net_amount_in,
+                amounts.curve_amount_out,
+            )?;
+        }
+
+        Ok(CheckedSwap {
+            amounts,
+            reserve_x,
+            reserve_y,
+            reserve_in_added,
+            reserve_out_removed,
+            window_start,
+            window_volume,
+        })
+    }
+
+    /// Checks and prices a swap through `check_swap`, then advances the oracle
+    /// and the same-slot tracking. Nothing is transferred; the caller moves
+    /// `amount_in` into the input vault and the returned `amount_out` to the
+    /// user. With `accrue_fees` the fee is added to `fees_accrued_x` or
+    /// `fees_accrued_y` rather than to the reserves the next swap is priced
+    /// against.
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_swap(
+        &mut self,
+        vault_x: u64,
+        vault_y: u64,
+        lp_supply: u64,
+        is_x: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        fee_discount_bps: u16,
+    ) -> Result<SwapAmounts> {
+        let clock = Clock::get()?;
+        let CheckedSwap {
+            amounts,
+            reserve_x,
+            reserve_y,
+            reserve_in_added,
+            reserve_out_removed,
+            window_start,
+            window_volume,
+        } = self.check_swap(
+            vault_x,
+            vault_y,
+            lp_supply,
+            is_x,
+            amount_in,
+            min_amount_out,
+            fee_discount_bps,
+            &clock,
+        )?;
+        let (reserve_in, reserve_out) = match is_x {
+            true => (reserve_x, reserve_y),
+            false => (reserve_y, reserve_x),
+        };
+
+        // Track what rounding the output down kept back, for `sweep_dust`
+        let dust = rounding_dust(
+            reserve_in,
+            reserve_out,
+            amounts.net_amount_in,
+            amounts.curve_amount_out,
+        )
+        .ok_or(AmmError::Overflow)?;
+        let dust_out = match is_x {
+            true => &mut self.dust_y,
+            false => &mut self.dust_x,
+        };
+        *dust_out = dust_out.checked_add(dust).ok_or(AmmError::Overflow)?;
+
+        // Close the oracle interval before the reserves change
+        self.update_oracle(reserve_x, reserve_y, clock.unix_timestamp);
+        self.last_swap_slot = clock.slot;
+        self.sequence = self.sequence.checked_add(1).ok_or(AmmError::Overflow)?;
+        self.volume_window_start = window_start;
+        self.window_volume = window_volume;
+        match is_x {
+            true => self.track_reserves(amount_in, 0, 0, amounts.amount_out),
+            false => self.track_reserves(0, amount_in, amounts.amount_out, 0),
+        }
+        // Whatever of the swap did not reach the reserves is the accrued fee
+        let fee_in = amount_in - reserve_in_added;
+        let fee_out = reserve_out_removed - amounts.amount_out;
+        let (fee_x, fee_y) = match is_x {
+            true => (fee_in, fee_out),
+            false => (fee_out, fee_in),
+        };
+        self.fees_accrued_x = self
+            .fees_accrued_x
+            .checked_add(fee_x)
+            .ok_or(AmmError::Overflow)?;
+        self.fees_accrued_y = self
+            .fees_accrued_y
+            .checked_add(fee_y)
+            .ok_or(AmmError::Overflow)?;
+
+        Ok(amounts)
+    }
+
+    /// Rejects a swap adding `amount_in` to `reserve_in` and taking `amount_out`
+    /// from `reserve_out` that lowers the invariant. The invariant of two `u64`
+    /// reserves always fits in a `u128`, see `invariant`, so the oversized
+    /// reserves a swap is rejected for with `ReservesTooLarge` are an input
+    /// reserve that no longer fits in a `u64`.
+    pub fn check_invariant(
+        reserve_in: u64,
+        reserve_out: u64,
+        amount_in: u64,
+        amount_out: u64,
+    ) -> Result<()> {
+        let k_before =
+            invariant(reserve_in as u128, reserve_out as u128).ok_or(AmmError::ReservesTooLarge)?;
+
+        // The curve holds reserves as u64, so the new input reserve must still fit
+        let reserve_in_after = reserve_in
+            .checked_add(amount_in)
+            .ok_or(AmmError::ReservesTooLarge)?;
+        let reserve_out_after = reserve_out
+            .checked_sub(amount_out)
+            .ok_or(AmmError::InsufficientBalance)?;
+
+        let k_after = invariant(reserve_in_after as u128, reserve_out_after as u128)
+            .ok_or(AmmError::ReservesTooLarge)?;
+
+        require!(k_after >= k_before, AmmError::CurveError);
+
+        Ok(())
+    }
+
+    /// Belt-and-suspenders check for pools where correctness outweighs compute
+    /// cost: recomputes the output with the in-crate constant-product math and
+    /// fails if it disagrees with the curve library beyond the tolerance.
+    pub fn verify_output(
+        &self,
+        reserve_in: u64,
+        reserve_out: u64,
+        net_amount_in: u64,
+        amount_out: u64,
+    ) -> Result<()> {
+        let expected = constant_product_out(reserve_in, reserve_out, net_amount_in, 0)
+            .ok_or(AmmError::CurveMismatch)?;
+
+        require!(
+            expected.abs_diff(amount_out) <= STRICT_VERIFY_TOLERANCE,
+            AmmError::CurveMismatch
+        );
+
+        Ok(())
+    }
+
+    /// Program that reached this instruction through CPI, or `None` for a
+    /// top-level instruction.
+    ///
+    /// A CPI'd instruction runs above the transaction-level stack height, and
+    /// the only caller instruction introspection exposes is the program of the
+    /// top-level instruction being executed, which is the one returned.
+    /// Programs further down a CPI chain are invisible, so a program that
+    /// relays arbitrary CPIs passes for every program it relays.
+    pub fn cpi_caller(instructions: &AccountInfo) -> Result<Option<Pubkey>> {
+        if get_stack_height() == TRANSACTION_LEVEL_STACK_HEIGHT {
+            return Ok(None);
+        }
+
+        let current = load_current_index_checked(instructions)?;
+        let caller = load_instruction_at_checked(current as usize, instructions)?.program_id;
+
+        Ok(Some(caller))
+    }
+
+    /// Rejects a swap reached through CPI, see `cpi_caller`, from a program
+    /// that is not in `allowed_callers`; with the list empty, any caller
+    /// passes. Top-level swaps, signed directly by a wallet, are always allowed.
+    pub fn check_caller(&self, instructions: &AccountInfo) -> Result<()> {
+        if self.allowed_callers.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(caller) = Self::cpi_caller(instructions)? {
+            require!(
+                self.allowed_callers.contains(&caller),
+                AmmError::CallerNotAllowed
+            );
+        }
+
+        Ok(())
+    }
+
+    /// If a swap reached through CPI, see `cpi_caller`, is fee-free: the
+    /// caller is `fee_exempt_caller` and `now` is before `fee_exempt_until`.
+    /// Top-level swaps and every other caller pay the normal fee.
+    pub fn fee_exempt(&self, instructions: &AccountInfo, now: i64) -> Result<bool> {
+        let Some(exempt_caller) = self.fee_exempt_caller else {
+            return Ok(false);
+        };
+        if now >= self.fee_exempt_until {
+            return Ok(false);
+        }
+
+        Ok(Self::cpi_caller(instructions)? == Some(exempt_caller))
+    }
+
+    pub fn check_authority(&self, authority: Pubkey) -> Result<()> {
+        let expected = self.authority.ok_or(AmmError::NoAuthoritySet)?;
+
+        require_keys_eq!(expected, authority, AmmError::InvalidAuthority);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_invariant_rejects_an_input_reserve_past_u64() {
+        let reserve_in = u64::MAX - 1_000;
+
+        // Filling the input reserve up to exactly u64::MAX still prices
+        assert!(Config::check_invariant(reserve_in, u64::MAX, 1_000, 1).is_ok());
+        assert_eq!(
+            Config::check_invariant(reserve_in, u64::MAX, 1_001, 1).unwrap_err(),
+            AmmError::ReservesTooLarge.into()
+        );
+        assert_eq!(
+            Config::check_invariant(reserve_in, u64::MAX, 1_000, 1 << 20).unwrap_err(),
+            AmmError::CurveError.into()
+        );
+    }
+
+    #[test]
+    fn observe_fee_price_averages_over_the_window() {
+        let mut config = Config::default();
+        let price = 2 * PRICE_SCALE;
+        let window = FEE_PRICE_WINDOW_SECS;
+
+        // The first observation only opens the window
+        assert_eq!(config.observe_fee_price(5 * PRICE_SCALE, 1_000), None);
+        assert_eq!(config.fee_price_ts, 1_000);
+
+        let cumulative = 5 * PRICE_SCALE + price * window as u128;
+        assert_eq!(
+            config.observe_fee_price(cumulative, 1_000 + window),
+            Some(price)
+        );
+        assert_eq!(config.fee_price_cumulative, cumulative);
+        assert_eq!(config.fee_price_ts, 1_000 + window);
+
+        // The accumulator wraps on overflow, which the average sees through
+        let wrapped = cumulative.wrapping_add(u128::MAX - price * window as u128 + 1);
+        let expected = (u128::MAX - price * window as u128 + 1) / (2 * window as u128);
+        assert_eq!(
+            config.observe_fee_price(wrapped, 1_000 + 3 * window),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn observe_fee_price_holds_on_a_stale_or_empty_accumulator() {
+        let mut config = Config::default();
+        let window = FEE_PRICE_WINDOW_SECS;
+
+        // An accumulator that never advanced, an empty pricing pool, gives no price
+        assert_eq!(config.observe_fee_price(0, 1_000), None);
+        assert_eq!(config.observe_fee_price(0, 1_000 + window), None);
+        assert_eq!(config.fee_price, 0);
+
+        let cumulative = PRICE_SCALE * window as u128;
+        assert_eq!(
+            config.observe_fee_price(cumulative, 1_000 + 2 * window),
+            Some(PRICE_SCALE)
+        );
+
+        // A pricing pool not updated since reports the same state, and the price holds
+        assert_eq!(
+            config.observe_fee_price(cumulative, 1_000 + 2 * window),
+            Some(PRICE_SCALE)
+        );
+        assert_eq!(config.fee_price_cumulative, cumulative);
+        assert_eq!(config.fee_price_ts, 1_000 + 2 * window);
+    }
+
+    #[test]
+    fn observe_fee_price_ignores_a_manipulated_spot_price() {
+        let mut config = Config::default();
+        let price = PRICE_SCALE;
+        let window = FEE_PRICE_WINDOW_SECS;
+        config.observe_fee_price(0, 1);
+        let settled = price * window as u128;
+        assert_eq!(config.observe_fee_price(settled, 1 + window), Some(price));
+
+        // The spot price is pushed to 100x and the pricing pool updated a second
+        // later; that is inside the window, so the observation is not taken
+        let pushed = settled + 100 * price;
+        assert_eq!(config.observe_fee_price(pushed, 2 + window), Some(price));
+        assert_eq!(config.fee_price_cumulative, settled);
+
+        // Held for one second of the next window, it barely moves the average
+        let cumulative = pushed + price * (window as u128 - 1);
+        let averaged = config
+            .observe_fee_price(cumulative, 1 + 2 * window)
+            .unwrap();
+        assert_eq!(averaged, price * (window as u128 + 99) / window as u128);
+        assert!(averaged < price + price / 10);
+    }
+
+    #[test]
+    fn fee_in_fee_token_converts_at_the_fee_price() {
+        // 1_000 of the paired token at 2.5 fee tokens each
+        assert_eq!(
+            Config::fee_in_fee_token(1_000, 5 * PRICE_SCALE / 2).unwrap(),
+            2_500
+        );
+        // A third of a fee token is rounded up to a whole one
+        assert_eq!(Config::fee_in_fee_token(1, PRICE_SCALE / 3).unwrap(), 1);
+        assert_eq!(Config::fee_in_fee_token(0, PRICE_SCALE).unwrap(), 0);
+        assert_eq!(
+            Config::fee_in_fee_token(u64::MAX, 2 * PRICE_SCALE).unwrap_err(),
+            AmmError::Overflow.into()
+        );
+    }
+
+    #[test]
+    fn window_volume_after_caps_each_window_and_resets_after_it() {
+        let mut config = Config {
+            daily_volume_cap: 1_000,
+            volume_window_start: 10_000,
+            window_volume: 600,
+            ..Config::default()
+        };
+        let window_end = 10_000 + VOLUME_WINDOW_SECS;
+
+        // Just under and exactly at the cap still fit in the open window
+        assert_eq!(
+            config.window_volume_after(399, window_end - 1).unwrap(),
+            (10_000, 999)
+        );
+        assert_eq!(
+            config.window_volume_after(400, window_end - 1).unwrap(),
+            (10_000, 1_000)
+        );
+        assert_eq!(
+            config.window_volume_after(401, window_end - 1).unwrap_err(),
+            AmmError::VolumeCapExceeded.into()
+        );
+
+        // Once the window has run its length, a swap opens a new one at `now`
+        assert_eq!(
+            config.window_volume_after(1_000, window_end).unwrap(),
+            (window_end, 1_000)
+        );
+        assert_eq!(
+            config.window_volume_after(1_001, window_end).unwrap_err(),
+            AmmError::VolumeCapExceeded.into()
+        );
+
+        // u64::MAX turns the cap off
+        config.daily_volume_cap = u64::MAX;
+        assert_eq!(
+            config
+                .window_volume_after(u64::MAX, window_end - 1)
+                .unwrap(),
+            (10_000, u64::MAX)
+        );
+    }
+
+    #[test]
+    fn check_fee_update_waits_out_the_interval() {
+        let mut config = Config::default();
+
+        // The first change after initialization is never limited
+        assert!(config.check_fee_update(1).is_ok());
+
+        config.last_fee_update_ts = 10_000;
+        assert_eq!(
+            config
+                .check_fee_update(10_000 + MIN_FEE_UPDATE_INTERVAL - 1)
+                .unwrap_err(),
+            AmmError::FeeUpdateTooFrequent.into()
+        );
+        assert!(config
+            .check_fee_update(10_000 + MIN_FEE_UPDATE_INTERVAL)
+            .is_ok());
+    }
 }
@@ -1,3 +1,11 @@
 pub mod config;
+pub mod pool_counter;
+pub mod position;
+pub mod staged_swap;
+pub mod swap_nonce;
 
 pub use config::*;
+pub use pool_counter::*;
+pub use position::*;
+pub use staged_swap::*;
+pub use swap_nonce::*;
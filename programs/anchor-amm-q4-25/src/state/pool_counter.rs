@@ -0,0 +1,32 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::AmmError;
+
+#[account]
+#[derive(InitSpace)]
+pub struct PoolCounter {
+    pub initializer: Pubkey, // Account whose initialized pools this counts
+    pub pools: u32,          // Pools initialized so far
+    pub max_pools: u32,      // Cap on `pools`, set by the program admin, u32::MAX disables
+    pub bump: u8,            // Bump seed for the counter account
+}
+
+impl PoolCounter {
+    /// Fills in a counter created on first use, leaving an existing one alone.
+    /// A new counter is uncapped until the admin sets a limit.
+    pub fn open(&mut self, initializer: Pubkey, bump: u8) {
+        if self.initializer == Pubkey::default() {
+            self.initializer = initializer;
+            self.max_pools = u32::MAX;
+            self.bump = bump;
+        }
+    }
+
+    /// Counts one more pool, failing once the cap is reached.
+    pub fn record_pool(&mut self) -> Result<()> {
+        require!(self.pools < self.max_pools, AmmError::PoolLimitReached);
+        self.pools += 1;
+
+        Ok(())
+    }
+}
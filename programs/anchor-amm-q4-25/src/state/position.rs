@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::AmmError, math::mul_div};
+
+#[account]
+#[derive(InitSpace)]
+pub struct Position {
+    pub owner: Pubkey,     // LP the position belongs to
+    pub config: Pubkey,    // Pool the position is in
+    pub entry_price: u128, // LP-weighted spot price of X in Y (PRICE_SCALE) at deposit
+    pub bump: u8,          // Bump seed for the position account
+}
+
+impl Position {
+    /// Folds a deposit minting `lp_amount` at `price` into the entry price,
+    /// weighted against the `lp_held` LP tokens the owner already had. LP
+    /// tokens moved in or out by transfer are weighted the same as deposits.
+    pub fn record_deposit(&mut self, price: u128, lp_held: u64, lp_amount: u64) -> Result<()> {
+        let lp_total = (lp_held as u128)
+            .checked_add(lp_amount as u128)
+            .ok_or(AmmError::Overflow)?;
+
+        let held_part =
+            mul_div(self.entry_price, lp_held as u128, lp_total).ok_or(AmmError::Overflow)?;
+        let new_part = mul_div(price, lp_amount as u128, lp_total).ok_or(AmmError::Overflow)?;
+
+        self.entry_price = held_part.checked_add(new_part).ok_or(AmmError::Overflow)?;
+
+        Ok(())
+    }
+}
